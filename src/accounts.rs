@@ -0,0 +1,316 @@
+use crate::storage::storage_err;
+use crate::{crypto, is_slug, now, Error};
+use rocket::http::Status;
+use rocket::request::{self, FromRequest, Request};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufWriter, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+const MAX_USERNAME_LENGTH: usize = 32;
+const MIN_PASSWORD_LENGTH: usize = 8;
+const MAX_PASSWORD_LENGTH: usize = 1024;
+
+// API tokens are only stored hashed, like edit and delete tokens.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Account {
+    pub username: String,
+    pub password_hash: String,
+    #[serde(default)]
+    pub tokens: Vec<String>,
+    pub created_at: u64,
+}
+
+#[derive(Default)]
+struct Registry {
+    accounts: HashMap<String, Account>,
+    // token hash -> username
+    tokens: HashMap<String, String>,
+}
+
+impl Registry {
+    fn forget_token(&mut self, hash: &str) -> Option<String> {
+        let username = self.tokens.remove(hash)?;
+        if let Some(account) = self.accounts.get_mut(&username) {
+            account.tokens.retain(|t| t != hash);
+        }
+        Some(username)
+    }
+}
+
+// Accounts change rarely, so the whole set is rewritten on every change
+// instead of keeping a log like the entries.
+#[derive(Clone)]
+pub struct Accounts {
+    registry: Arc<Mutex<Registry>>,
+    path: Option<PathBuf>,
+}
+
+impl Accounts {
+    pub fn init() -> Accounts {
+        Accounts {
+            registry: Arc::new(Mutex::new(Registry::default())),
+            path: None,
+        }
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> Result<Accounts, Error> {
+        let path = path.as_ref();
+        let accounts: Vec<Account> = match fs::read(path) {
+            Ok(data) => serde_json::from_slice(&data).map_err(storage_err)?,
+            Err(e) if e.kind() == ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(storage_err(e)),
+        };
+
+        let mut registry = Registry::default();
+        for account in accounts {
+            for token in &account.tokens {
+                registry
+                    .tokens
+                    .insert(token.clone(), account.username.clone());
+            }
+            registry.accounts.insert(account.username.clone(), account);
+        }
+        Ok(Accounts {
+            registry: Arc::new(Mutex::new(registry)),
+            path: Some(path.to_path_buf()),
+        })
+    }
+
+    // Password hashing is slow on purpose, so it runs outside the lock.
+    pub fn register(&self, username: &str, password: &str) -> Result<Account, Error> {
+        if !is_slug(username, MAX_USERNAME_LENGTH) {
+            return Err(Error::InvalidUsername(MAX_USERNAME_LENGTH));
+        }
+        let length = password.chars().count();
+        if length < MIN_PASSWORD_LENGTH {
+            return Err(Error::WeakPassword(MIN_PASSWORD_LENGTH));
+        }
+        if length > MAX_PASSWORD_LENGTH {
+            return Err(Error::PasswordTooLong(MAX_PASSWORD_LENGTH));
+        }
+        if self
+            .registry
+            .lock()
+            .unwrap()
+            .accounts
+            .contains_key(username)
+        {
+            return Err(Error::UsernameTaken);
+        }
+        let account = Account {
+            username: username.to_string(),
+            password_hash: crypto::hash_password(password)?,
+            tokens: Vec::new(),
+            created_at: now(),
+        };
+
+        let mut registry = self.registry.lock().unwrap();
+        if registry.accounts.contains_key(username) {
+            return Err(Error::UsernameTaken);
+        }
+        registry
+            .accounts
+            .insert(username.to_string(), account.clone());
+        if let Err(e) = self.save(&registry) {
+            registry.accounts.remove(username);
+            return Err(e);
+        }
+        Ok(account)
+    }
+
+    // Every login hands out a new token, so several clients can be logged in
+    // at once and log out independently.
+    pub fn login(&self, username: &str, password: &str) -> Result<String, Error> {
+        // no account can have a longer one, so don't spend a hash on it
+        if password.chars().count() > MAX_PASSWORD_LENGTH {
+            return Err(Error::InvalidCredentials);
+        }
+        let hash = self
+            .registry
+            .lock()
+            .unwrap()
+            .accounts
+            .get(username)
+            .map(|account| account.password_hash.clone());
+        match hash {
+            Some(hash) if crypto::verify_password(password, &hash) => {}
+            _ => return Err(Error::InvalidCredentials),
+        }
+
+        let token = crypto::generate_token();
+        let hash = crypto::hash_token(&token);
+        let mut registry = self.registry.lock().unwrap();
+        let account = registry
+            .accounts
+            .get_mut(username)
+            .ok_or(Error::InvalidCredentials)?;
+        account.tokens.push(hash.clone());
+        registry.tokens.insert(hash.clone(), username.to_string());
+        if let Err(e) = self.save(&registry) {
+            registry.forget_token(&hash);
+            return Err(e);
+        }
+        Ok(token)
+    }
+
+    pub fn logout(&self, token: &str) -> Result<(), Error> {
+        let hash = crypto::hash_token(token);
+        let mut registry = self.registry.lock().unwrap();
+        // if the save fails the token stays on disk, so it stays valid here
+        // too instead of coming back on the next start
+        if let Some(username) = registry.forget_token(&hash) {
+            if let Err(e) = self.save(&registry) {
+                if let Some(account) = registry.accounts.get_mut(&username) {
+                    account.tokens.push(hash.clone());
+                }
+                registry.tokens.insert(hash, username);
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn authenticate(&self, token: &str) -> Option<String> {
+        let hash = crypto::hash_token(token);
+        self.registry.lock().unwrap().tokens.get(&hash).cloned()
+    }
+
+    fn save(&self, registry: &Registry) -> Result<(), Error> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(storage_err)?;
+        }
+        let mut tmp = path.clone();
+        tmp.set_extension("tmp");
+
+        let accounts: Vec<&Account> = registry.accounts.values().collect();
+        let mut out = BufWriter::new(File::create(&tmp).map_err(storage_err)?);
+        serde_json::to_writer(&mut out, &accounts).map_err(storage_err)?;
+        let out = out.into_inner().map_err(|e| storage_err(e.into_error()))?;
+        out.sync_all().map_err(storage_err)?;
+        fs::rename(&tmp, path).map_err(storage_err)
+    }
+}
+
+// A caller authenticated by an `Authorization: Bearer` API token.
+pub struct User {
+    pub username: String,
+    pub token: String,
+}
+
+// For routes open to everyone that still record who called them. A bearer
+// token that doesn't check out is rejected rather than treated as anonymous.
+pub struct MaybeUser(pub Option<User>);
+
+impl MaybeUser {
+    pub fn username(&self) -> Option<&str> {
+        self.0.as_ref().map(|user| user.username.as_str())
+    }
+}
+
+pub fn bearer(req: &Request<'_>) -> request::Outcome<Option<User>, ()> {
+    let header = match req.headers().get_one("Authorization") {
+        Some(header) => header,
+        None => return request::Outcome::Success(None),
+    };
+    let token = match header.strip_prefix("Bearer ") {
+        Some(token) => token.trim(),
+        None => return request::Outcome::Error((Status::Unauthorized, ())),
+    };
+    let username = req
+        .rocket()
+        .state::<Accounts>()
+        .and_then(|accounts| accounts.authenticate(token));
+    match username {
+        Some(username) => request::Outcome::Success(Some(User {
+            username,
+            token: token.to_string(),
+        })),
+        None => request::Outcome::Error((Status::Unauthorized, ())),
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for User {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        bearer(req).and_then(|user| match user {
+            Some(user) => request::Outcome::Success(user),
+            None => request::Outcome::Error((Status::Unauthorized, ())),
+        })
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for MaybeUser {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        bearer(req).map(MaybeUser)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Accounts;
+
+    #[test]
+    fn accounts_survive_reopen() {
+        let dir = std::env::temp_dir().join(format!("pastebin-accounts-{}", std::process::id()));
+        let path = dir.join("accounts.json");
+        let _ = std::fs::remove_file(&path);
+
+        let accounts = Accounts::open(&path).unwrap();
+        assert!(accounts.register("alice", "short").is_err());
+        let long = "x".repeat(2000);
+        assert!(accounts.register("alice", &long).is_err());
+        accounts.register("alice", "correct horse").unwrap();
+        assert!(accounts.register("alice", "another one").is_err());
+        assert!(accounts.login("alice", "wrong password").is_err());
+        assert!(accounts.login("alice", &long).is_err());
+        let token = accounts.login("alice", "correct horse").unwrap();
+        let revoked = accounts.login("alice", "correct horse").unwrap();
+        accounts.logout(&revoked).unwrap();
+        drop(accounts);
+
+        let accounts = Accounts::open(&path).unwrap();
+        assert_eq!(accounts.authenticate(&token).as_deref(), Some("alice"));
+        assert!(accounts.authenticate(&revoked).is_none());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn failed_saves_change_nothing() {
+        let dir = std::env::temp_dir().join(format!("pastebin-failed-save-{}", std::process::id()));
+        let path = dir.join("accounts.json");
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let accounts = Accounts::open(&path).unwrap();
+        accounts.register("alice", "correct horse").unwrap();
+        let token = accounts.login("alice", "correct horse").unwrap();
+
+        // the temporary file can't be created over a directory
+        let tmp = dir.join("accounts.tmp");
+        std::fs::create_dir(&tmp).unwrap();
+        assert!(accounts.login("alice", "correct horse").is_err());
+        assert_eq!(accounts.registry.lock().unwrap().tokens.len(), 1);
+        assert!(accounts.logout(&token).is_err());
+        assert_eq!(accounts.authenticate(&token).as_deref(), Some("alice"));
+        std::fs::remove_dir(&tmp).unwrap();
+
+        accounts.logout(&token).unwrap();
+        drop(accounts);
+        let accounts = Accounts::open(&path).unwrap();
+        assert!(accounts.authenticate(&token).is_none());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
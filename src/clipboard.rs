@@ -0,0 +1,326 @@
+use crate::entry::{Entry, Revision, Visibility};
+use crate::index::{listing_key, Indexed, ListingKey};
+use crate::search::Query;
+use crate::storage::{LogStorage, MemoryStorage, Storage};
+use crate::{crypto, now, Config, Error};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+const MAX_ID_ATTEMPTS: usize = 16;
+
+// How a caller proves it may change an entry: with the token handed out on
+// creation, or as the account that owns it.
+pub enum Access {
+    Token(String),
+    Owner(String),
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Quota {
+    pub max_entries: Option<usize>,
+    pub max_size: Option<u64>,
+}
+
+#[derive(Clone)]
+pub struct Clipboard {
+    entries: Arc<Mutex<Indexed>>,
+    quota: Quota,
+}
+
+impl Clipboard {
+    pub fn init() -> Clipboard {
+        Clipboard::with_storage(MemoryStorage::default())
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> Result<Clipboard, Error> {
+        Ok(Clipboard::with_storage(LogStorage::open(path)?))
+    }
+
+    pub fn with_storage(storage: impl Storage + 'static) -> Clipboard {
+        Clipboard {
+            entries: Arc::new(Mutex::new(Indexed::new(Box::new(storage)))),
+            quota: Quota::default(),
+        }
+    }
+
+    pub fn with_quota(self, quota: Quota) -> Clipboard {
+        Clipboard { quota, ..self }
+    }
+
+    // Expired entries still count until the reaper gets to them.
+    fn check_quota(
+        &self,
+        entries: &Indexed,
+        new_entries: usize,
+        new_bytes: u64,
+    ) -> Result<(), Error> {
+        let (count, size) = entries.totals();
+        let too_many = self
+            .quota
+            .max_entries
+            .is_some_and(|max| count + new_entries > max);
+        let too_big = self
+            .quota
+            .max_size
+            .is_some_and(|max| size + new_bytes > max);
+        if too_many || too_big {
+            return Err(Error::StoreFull);
+        }
+        Ok(())
+    }
+
+    pub fn add(&self, entry: Entry) -> Result<(), Error> {
+        let mut entries = self.entries.lock().unwrap();
+        // an expired entry that hasn't been reaped yet doesn't own its id
+        if let Some(old) = entries.get(&entry.id) {
+            if old.is_expired(now()) {
+                entries.remove(&old.id)?;
+            }
+        }
+        self.check_quota(&entries, 1, entry.size())?;
+        entries.insert(entry)
+    }
+
+    pub fn add_with_generated_id(
+        &self,
+        mut entry: Entry,
+        config: &Config,
+    ) -> Result<String, Error> {
+        let mut entries = self.entries.lock().unwrap();
+        self.check_quota(&entries, 1, entry.size())?;
+        for _ in 0..MAX_ID_ATTEMPTS {
+            let id = config.generate_id();
+            if !entries.contains(&id) {
+                entry.id = id.clone();
+                entries.insert(entry)?;
+                return Ok(id);
+            }
+        }
+        Err(Error::IdSpaceExhausted)
+    }
+
+    pub fn get(&self, id: &str) -> Option<Entry> {
+        let entry = self.entries.lock().unwrap().get(id)?;
+        (!entry.is_expired(now())).then_some(entry)
+    }
+
+    // Entries hidden from `viewer` look the same as missing ones.
+    pub fn get_visible(&self, id: &str, viewer: Option<&str>) -> Option<Entry> {
+        self.get(id).filter(|entry| entry.visible_to(viewer))
+    }
+
+    // Looks up a revision for display. Plaintext revisions count as a read,
+    // encrypted entries are only used up by a successful decrypt.
+    pub fn read(
+        &self,
+        id: &str,
+        rev: Option<usize>,
+        viewer: Option<&str>,
+    ) -> Result<(Entry, usize), Error> {
+        let mut entry = self.get_visible(id, viewer).ok_or(Error::NotFound)?;
+        let rev = rev.unwrap_or(entry.revision_number());
+        let revision = entry.revision(rev).ok_or(Error::NotFound)?;
+        if !revision.encrypted {
+            entry = self.consume(id)?;
+        }
+        Ok((entry, rev))
+    }
+
+    // Lets a caller turn away an edit before sealing the new content, which
+    // runs the key derivation for encrypted entries.
+    pub fn check_edit(&self, id: &str, access: &Access) -> Result<(), Error> {
+        let entries = self.entries.lock().unwrap();
+        let entry = entries
+            .get(id)
+            .filter(|entry| !entry.is_expired(now()))
+            .ok_or(Error::NotFound)?;
+        check_access(&entry, &entry.edit_token, access)
+    }
+
+    // Edits never replace content, the previous revision is kept in the
+    // entry's history.
+    pub fn update(&self, id: &str, revision: Revision, access: Access) -> Result<usize, Error> {
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries
+            .get(id)
+            .filter(|entry| !entry.is_expired(now()))
+            .ok_or(Error::NotFound)?;
+        check_access(&entry, &entry.edit_token, &access)?;
+        self.check_quota(&entries, 0, revision.content.len() as u64)?;
+        entries.push_revision(id, revision)?;
+        Ok(entry.revision_number() + 1)
+    }
+
+    // Counts one successful read against the entry's read limit and burns it
+    // once the limit is used up. Concurrent readers race here under the lock,
+    // so only as many of them succeed as there are reads left.
+    pub fn consume(&self, id: &str) -> Result<Entry, Error> {
+        let mut entries = self.entries.lock().unwrap();
+        let mut entry = entries
+            .get(id)
+            .filter(|entry| !entry.is_expired(now()))
+            .ok_or(Error::NotFound)?;
+        entry.views += 1;
+        match entry.reads_left {
+            None => entries.count_view(id)?,
+            Some(0..=1) => {
+                entries.remove(id)?;
+            }
+            Some(n) => {
+                entry.reads_left = Some(n - 1);
+                entries.replace(entry.clone())?;
+            }
+        }
+        Ok(entry)
+    }
+
+    pub fn set_visibility(
+        &self,
+        id: &str,
+        visibility: Visibility,
+        shared_with: Vec<String>,
+        access: Access,
+    ) -> Result<(), Error> {
+        let mut entries = self.entries.lock().unwrap();
+        let mut entry = entries
+            .get(id)
+            .filter(|entry| !entry.is_expired(now()))
+            .ok_or(Error::NotFound)?;
+        check_access(&entry, &entry.edit_token, &access)?;
+        if visibility == Visibility::Private && entry.owner.is_none() {
+            return Err(Error::MissingOwner);
+        }
+        entry.visibility = visibility;
+        entry.shared_with = shared_with;
+        entries.replace(entry)?;
+        Ok(())
+    }
+
+    pub fn delete(&self, id: &str, access: Access) -> Result<(), Error> {
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries
+            .get(id)
+            .filter(|entry| !entry.is_expired(now()))
+            .ok_or(Error::NotFound)?;
+        check_access(&entry, &entry.delete_token, &access)?;
+        entries.remove(id)?;
+        Ok(())
+    }
+
+    // Newest first.
+    pub fn owned_by<T>(&self, owner: &str, summarize: impl Fn(&Entry) -> T) -> Vec<T> {
+        let entries = self.entries.lock().unwrap();
+        let now = now();
+        let mut owned: Vec<&Entry> = entries
+            .entries()
+            .filter(|entry| entry.owner.as_deref() == Some(owner) && !entry.is_expired(now))
+            .collect();
+        owned.sort_by(|a, b| b.created_at().cmp(&a.created_at()).then(a.id.cmp(&b.id)));
+        owned.into_iter().map(summarize).collect()
+    }
+
+    // One page of public entries, newest first, and the cursor for the next
+    // page if there is one. Listings only show metadata, `summarize` picks it
+    // out under the lock so the content is never copied.
+    pub fn public_entries<T>(
+        &self,
+        cursor: Option<&ListingKey>,
+        limit: usize,
+        summarize: impl Fn(&Entry) -> T,
+    ) -> (Vec<T>, Option<ListingKey>) {
+        let entries = self.entries.lock().unwrap();
+        page(&entries, entries.public(cursor), limit, summarize)
+    }
+
+    pub fn tagged<T>(
+        &self,
+        tag: &str,
+        cursor: Option<&ListingKey>,
+        limit: usize,
+        summarize: impl Fn(&Entry) -> T,
+    ) -> (Vec<T>, Option<ListingKey>) {
+        let entries = self.entries.lock().unwrap();
+        page(&entries, entries.tagged(tag, cursor), limit, summarize)
+    }
+
+    // Public tags with the number of entries carrying them, most used first.
+    // Expired entries are still counted until the reaper removes them.
+    pub fn tag_counts(&self) -> Vec<(String, usize)> {
+        let entries = self.entries.lock().unwrap();
+        let mut tags: Vec<(String, usize)> = entries
+            .tags()
+            .map(|(tag, count)| (tag.clone(), count))
+            .collect();
+        tags.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        tags
+    }
+
+    pub fn search<T>(
+        &self,
+        query: &Query,
+        limit: usize,
+        summarize: impl Fn(&Entry, f64) -> T,
+    ) -> Vec<T> {
+        let entries = self.entries.lock().unwrap();
+        let now = now();
+        entries
+            .search(query)
+            .into_iter()
+            .filter_map(|(id, score)| Some((entries.get_ref(&id)?, score)))
+            .filter(|(entry, _)| !entry.is_expired(now))
+            .take(limit)
+            .map(|(entry, score)| summarize(entry, score))
+            .collect()
+    }
+
+    pub fn flush_views(&self) -> Result<(), Error> {
+        self.entries.lock().unwrap().flush_views()
+    }
+
+    pub fn purge_expired(&self, now: u64) -> Result<usize, Error> {
+        let mut entries = self.entries.lock().unwrap();
+        let expired: Vec<String> = entries
+            .entries()
+            .filter(|entry| entry.is_expired(now))
+            .map(|entry| entry.id.clone())
+            .collect();
+        for id in &expired {
+            entries.remove(id)?;
+        }
+        Ok(expired.len())
+    }
+}
+
+fn page<'a, T>(
+    entries: &Indexed,
+    keys: impl Iterator<Item = &'a ListingKey>,
+    limit: usize,
+    summarize: impl Fn(&Entry) -> T,
+) -> (Vec<T>, Option<ListingKey>) {
+    let now = now();
+    let mut page: Vec<&Entry> = keys
+        .filter_map(|(_, id)| entries.get_ref(id))
+        .filter(|entry| !entry.is_expired(now))
+        .take(limit + 1)
+        .collect();
+    let next = if page.len() > limit {
+        page.truncate(limit);
+        page.last().map(|entry| listing_key(entry))
+    } else {
+        None
+    };
+    (page.into_iter().map(summarize).collect(), next)
+}
+
+fn check_access(entry: &Entry, hash: &Option<String>, access: &Access) -> Result<(), Error> {
+    let allowed = match access {
+        Access::Token(token) => hash
+            .as_ref()
+            .is_some_and(|hash| crypto::verify(hash, &crypto::hash_token(token))),
+        Access::Owner(user) => entry.owner.as_ref() == Some(user),
+    };
+    match allowed {
+        true => Ok(()),
+        false => Err(Error::InvalidToken),
+    }
+}
@@ -0,0 +1,122 @@
+use crate::Error;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use base64::engine::general_purpose::{STANDARD as BASE64, URL_SAFE_NO_PAD as BASE64_URL};
+use base64::Engine;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+const NONCE_LEN: usize = 24;
+const SALT_LEN: usize = 16;
+
+pub fn generate_salt() -> String {
+    let mut salt = [0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    BASE64.encode(salt)
+}
+
+// Stretches a user password with Argon2id (default parameters: 19 MiB,
+// 2 passes) and the entry's salt into 64 bytes. The first half is the cipher
+// key, the second half is the verifier the server stores to check passwords
+// without ever holding the key itself.
+pub fn derive_key(password: &str, salt: &str) -> Result<(Key, String), Error> {
+    let salt = BASE64.decode(salt).map_err(|_| Error::KeyDerivation)?;
+    let mut out = [0u8; 64];
+    Argon2::default()
+        .hash_password_into(password.as_bytes(), &salt, &mut out)
+        .map_err(|_| Error::KeyDerivation)?;
+
+    let (key, verifier) = out.split_at(32);
+    Ok((*Key::from_slice(key), BASE64.encode(verifier)))
+}
+
+// Tokens are random 256 bit secrets, so a plain hash is enough to keep them
+// out of storage; they don't need the password KDF.
+pub fn generate_token() -> String {
+    let mut token = [0u8; 32];
+    OsRng.fill_bytes(&mut token);
+    BASE64_URL.encode(token)
+}
+
+pub fn hash_token(token: &str) -> String {
+    BASE64.encode(Sha256::digest(token.as_bytes()))
+}
+
+pub fn verify(expected: &str, actual: &str) -> bool {
+    expected.as_bytes().ct_eq(actual.as_bytes()).into()
+}
+
+// Account passwords are stored as PHC strings, which carry their own salt
+// and Argon2 parameters.
+pub fn hash_password(password: &str) -> Result<String, Error> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|_| Error::KeyDerivation)
+}
+
+pub fn verify_password(password: &str, hash: &str) -> bool {
+    PasswordHash::new(hash).is_ok_and(|hash| {
+        Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok()
+    })
+}
+
+// Output is nonce || ciphertext || tag.
+pub fn encrypt(key: &Key, data: &[u8]) -> Result<Vec<u8>, Error> {
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ct = XChaCha20Poly1305::new(key)
+        .encrypt(&nonce, data)
+        .map_err(|_| Error::Encryption)?;
+
+    let mut out = nonce.to_vec();
+    out.extend_from_slice(&ct);
+    Ok(out)
+}
+
+pub fn decrypt(key: &Key, data: &[u8]) -> Result<Vec<u8>, Error> {
+    if data.len() < NONCE_LEN {
+        return Err(Error::Decryption);
+    }
+
+    let (nonce, ct) = data.split_at(NONCE_LEN);
+    XChaCha20Poly1305::new(key)
+        .decrypt(XNonce::from_slice(nonce), ct)
+        .map_err(|_| Error::Decryption)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decrypt, derive_key, encrypt, generate_salt, verify};
+
+    #[test]
+    fn roundtrip() {
+        let salt = generate_salt();
+        let (key, verifier) = derive_key("short", &salt).unwrap();
+        let pt = "0123456789abcdef ünïcödé".as_bytes();
+        let ct = encrypt(&key, pt).unwrap();
+        assert_eq!(pt, decrypt(&key, &ct).unwrap());
+
+        let (wrong, wrong_verifier) = derive_key("wrong", &salt).unwrap();
+        assert!(decrypt(&wrong, &ct).is_err());
+        assert!(!verify(&verifier, &wrong_verifier));
+        assert!(verify(&verifier, &derive_key("short", &salt).unwrap().1));
+
+        let (other_salt, _) = derive_key("short", &generate_salt()).unwrap();
+        assert!(decrypt(&other_salt, &ct).is_err());
+    }
+
+    #[test]
+    fn tampered_ciphertext() {
+        let (key, _) = derive_key("supersecreptkey!", &generate_salt()).unwrap();
+        let mut ct = encrypt(&key, b"attack at dawn").unwrap();
+        let last = ct.len() - 1;
+        ct[last] ^= 1;
+        assert!(decrypt(&key, &ct).is_err());
+    }
+}
@@ -0,0 +1,132 @@
+use serde::Serialize;
+use similar::{ChangeTag, TextDiff};
+use std::ops::Range;
+use std::time::Duration;
+
+const CONTEXT_LINES: usize = 3;
+// Diffing is quadratic at worst. Past this the diff is only approximated,
+// it can then report more lines as changed than actually are.
+const DIFF_TIMEOUT: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Serialize)]
+pub struct Diff {
+    pub unified: String,
+    pub hunks: Vec<Hunk>,
+}
+
+// Line numbers are 1-based like in the unified hunk header, where an empty
+// range starts at the line before it.
+#[derive(Debug, Serialize)]
+pub struct Hunk {
+    pub old_start: usize,
+    pub old_lines: usize,
+    pub new_start: usize,
+    pub new_lines: usize,
+    pub lines: Vec<Line>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Line {
+    pub kind: LineKind,
+    pub old_line: Option<usize>,
+    pub new_line: Option<usize>,
+    pub content: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LineKind {
+    Context,
+    Delete,
+    Insert,
+}
+
+pub fn diff(old_name: &str, old: &str, new_name: &str, new: &str) -> Diff {
+    let diff = TextDiff::configure()
+        .timeout(DIFF_TIMEOUT)
+        .diff_lines(old, new);
+    let mut unified = diff.unified_diff();
+    unified
+        .context_radius(CONTEXT_LINES)
+        .header(old_name, new_name);
+
+    let hunks = unified
+        .iter_hunks()
+        .map(|hunk| {
+            let ops = hunk.ops();
+            let (first, last) = (&ops[0], &ops[ops.len() - 1]);
+            let old_range = first.old_range().start..last.old_range().end;
+            let new_range = first.new_range().start..last.new_range().end;
+
+            Hunk {
+                old_start: hunk_start(&old_range),
+                old_lines: old_range.len(),
+                new_start: hunk_start(&new_range),
+                new_lines: new_range.len(),
+                lines: hunk
+                    .iter_changes()
+                    .map(|change| Line {
+                        kind: match change.tag() {
+                            ChangeTag::Equal => LineKind::Context,
+                            ChangeTag::Delete => LineKind::Delete,
+                            ChangeTag::Insert => LineKind::Insert,
+                        },
+                        old_line: change.old_index().map(|i| i + 1),
+                        new_line: change.new_index().map(|i| i + 1),
+                        content: change.value().trim_end_matches('\n').to_string(),
+                    })
+                    .collect(),
+            }
+        })
+        .collect();
+
+    Diff {
+        unified: unified.to_string(),
+        hunks,
+    }
+}
+
+fn hunk_start(range: &Range<usize>) -> usize {
+    if range.is_empty() {
+        range.start
+    } else {
+        range.start + 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::diff;
+
+    #[test]
+    fn single_hunk() {
+        let old = "a\nb\nc\nd\n";
+        let new = "a\nb\nC\nd\ne\n";
+        let diff = diff("old", old, "new", new);
+
+        assert!(diff
+            .unified
+            .starts_with("--- old\n+++ new\n@@ -1,4 +1,5 @@\n"));
+        assert_eq!(diff.hunks.len(), 1);
+        let hunk = &diff.hunks[0];
+        assert_eq!((hunk.old_start, hunk.old_lines), (1, 4));
+        assert_eq!((hunk.new_start, hunk.new_lines), (1, 5));
+        let changed: Vec<_> = hunk
+            .lines
+            .iter()
+            .filter(|line| line.old_line.is_none() || line.new_line.is_none())
+            .map(|line| line.content.as_str())
+            .collect();
+        assert_eq!(changed, ["c", "C", "e"]);
+    }
+
+    #[test]
+    fn slow_diffs_give_up() {
+        let old: String = (0..40_000).map(|i| format!("{}\n", i % 2)).collect();
+        let new: String = (0..40_000).map(|i| format!("{}\n", i % 3)).collect();
+        let start = std::time::Instant::now();
+        let diff = diff("old", &old, "new", &new);
+        assert!(start.elapsed().as_secs() < 10);
+        assert!(!diff.hunks.is_empty());
+    }
+}
@@ -0,0 +1,459 @@
+use crate::{crypto, is_slug, now, Error};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use rocket::data::Capped;
+use rocket::fs::TempFile;
+use rocket::http::ContentType;
+use serde::{Deserialize, Serialize};
+use std::iter;
+
+const MAX_TITLE_LENGTH: usize = 200;
+const MAX_LANGUAGE_LENGTH: usize = 32;
+const MAX_TAG_LENGTH: usize = 32;
+const MAX_TAGS: usize = 10;
+
+// Content is raw bytes, for encrypted revisions nonce || ciphertext.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(try_from = "StoredRevision", into = "StoredRevision")]
+pub struct Revision {
+    pub content: Vec<u8>,
+    pub encrypted: bool,
+    pub salt: Option<String>,
+    pub verifier: Option<String>,
+    pub mime: Option<String>,
+    pub filename: Option<String>,
+    pub created_at: u64,
+}
+
+// Content is stored base64 encoded. Revisions written before content became
+// bytes have no `encoding` and hold plain text as is, only their encrypted
+// content is base64.
+#[derive(Deserialize, Serialize)]
+struct StoredRevision {
+    content: String,
+    #[serde(default)]
+    encoding: Option<Encoding>,
+    encrypted: bool,
+    salt: Option<String>,
+    verifier: Option<String>,
+    #[serde(default)]
+    mime: Option<String>,
+    #[serde(default)]
+    filename: Option<String>,
+    #[serde(default)]
+    created_at: u64,
+}
+
+impl TryFrom<StoredRevision> for Revision {
+    type Error = Error;
+
+    fn try_from(stored: StoredRevision) -> Result<Revision, Error> {
+        let content = match (stored.encoding, stored.encrypted) {
+            (Some(Encoding::Base64), _) | (None, true) => BASE64
+                .decode(stored.content)
+                .map_err(|_| Error::InvalidEncoding)?,
+            (Some(Encoding::Utf8), _) | (None, false) => stored.content.into_bytes(),
+        };
+        Ok(Revision {
+            content,
+            encrypted: stored.encrypted,
+            salt: stored.salt,
+            verifier: stored.verifier,
+            mime: stored.mime,
+            filename: stored.filename,
+            created_at: stored.created_at,
+        })
+    }
+}
+
+impl From<Revision> for StoredRevision {
+    fn from(revision: Revision) -> StoredRevision {
+        StoredRevision {
+            content: BASE64.encode(revision.content),
+            encoding: Some(Encoding::Base64),
+            encrypted: revision.encrypted,
+            salt: revision.salt,
+            verifier: revision.verifier,
+            mime: revision.mime,
+            filename: revision.filename,
+            created_at: revision.created_at,
+        }
+    }
+}
+
+impl Revision {
+    // `None` for encrypted or binary content.
+    pub fn text(&self) -> Option<&str> {
+        if self.encrypted {
+            return None;
+        }
+        std::str::from_utf8(&self.content).ok()
+    }
+
+    pub fn content_type(&self) -> ContentType {
+        let fallback = match self.text() {
+            Some(_) => ContentType::Plain,
+            None => ContentType::Binary,
+        };
+        self.mime
+            .as_deref()
+            .and_then(ContentType::parse_flexible)
+            .unwrap_or(fallback)
+    }
+}
+
+// Public entries are listed, unlisted ones only found by id, and private
+// ones only shown to their owner and the accounts they are shared with.
+#[derive(Debug, Default, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, FromFormField)]
+#[serde(rename_all = "snake_case")]
+pub enum Visibility {
+    Public,
+    #[default]
+    Unlisted,
+    Private,
+}
+
+// `current` is flattened so entries written before revisions existed still
+// load, with an empty history.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Entry {
+    pub id: String,
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub language: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub views: u64,
+    #[serde(flatten)]
+    pub current: Revision,
+    #[serde(default)]
+    pub history: Vec<Revision>,
+    pub edit_token: Option<String>,
+    pub delete_token: Option<String>,
+    pub expires_at: Option<u64>,
+    pub reads_left: Option<u32>,
+    #[serde(default)]
+    pub owner: Option<String>,
+    #[serde(default)]
+    pub visibility: Visibility,
+    #[serde(default)]
+    pub shared_with: Vec<String>,
+}
+
+impl Entry {
+    pub fn new(id: String, revision: Revision) -> Entry {
+        Entry {
+            id,
+            title: None,
+            language: None,
+            tags: Vec::new(),
+            views: 0,
+            current: revision,
+            history: Vec::new(),
+            edit_token: None,
+            delete_token: None,
+            expires_at: None,
+            reads_left: None,
+            owner: None,
+            visibility: Visibility::default(),
+            shared_with: Vec::new(),
+        }
+    }
+
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|at| at <= now)
+    }
+
+    pub fn visible_to(&self, user: Option<&str>) -> bool {
+        match (self.visibility, user) {
+            (Visibility::Public | Visibility::Unlisted, _) => true,
+            (Visibility::Private, None) => false,
+            (Visibility::Private, Some(user)) => {
+                self.owner.as_deref() == Some(user) || self.shared_with.iter().any(|u| u == user)
+            }
+        }
+    }
+
+    // Revisions are numbered from 1, the current one is the highest.
+    pub fn revision_number(&self) -> usize {
+        self.history.len() + 1
+    }
+
+    pub fn revision(&self, n: usize) -> Option<&Revision> {
+        self.revisions().nth(n.checked_sub(1)?)
+    }
+
+    pub fn revisions(&self) -> impl Iterator<Item = &Revision> {
+        self.history.iter().chain(iter::once(&self.current))
+    }
+
+    pub fn created_at(&self) -> u64 {
+        self.revisions().next().map_or(0, |rev| rev.created_at)
+    }
+
+    // Stored bytes over all revisions.
+    pub fn size(&self) -> u64 {
+        self.revisions().map(|rev| rev.content.len() as u64).sum()
+    }
+
+    pub fn push_revision(&mut self, revision: Revision) {
+        let old = std::mem::replace(&mut self.current, revision);
+        self.history.push(old);
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Encoding {
+    #[default]
+    Utf8,
+    Base64,
+}
+
+// JSON carries binary content base64 encoded, flagged by `encoding`.
+#[derive(Debug, Deserialize)]
+struct JsonContent {
+    content: String,
+    #[serde(default)]
+    encoding: Encoding,
+    encrypted: bool,
+    key: Option<String>,
+    mime: Option<String>,
+    filename: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(try_from = "JsonContent")]
+pub struct EntryContent {
+    pub content: Vec<u8>,
+    pub encrypted: bool,
+    pub key: Option<String>,
+    pub mime: Option<String>,
+    pub filename: Option<String>,
+}
+
+impl TryFrom<JsonContent> for EntryContent {
+    type Error = Error;
+
+    fn try_from(json: JsonContent) -> Result<EntryContent, Error> {
+        let content = match json.encoding {
+            Encoding::Utf8 => json.content.into_bytes(),
+            Encoding::Base64 => BASE64
+                .decode(json.content)
+                .map_err(|_| Error::InvalidEncoding)?,
+        };
+        Ok(EntryContent {
+            content,
+            encrypted: json.encrypted,
+            key: json.key,
+            mime: json.mime,
+            filename: json.filename,
+        })
+    }
+}
+
+impl EntryContent {
+    pub fn seal(self) -> Result<Revision, Error> {
+        let mut revision = Revision {
+            content: self.content,
+            encrypted: self.encrypted,
+            salt: None,
+            verifier: None,
+            mime: self.mime,
+            filename: self.filename.as_deref().map(sanitize_filename),
+            created_at: now(),
+        };
+        if self.encrypted {
+            let password = self.key.ok_or(Error::MissingKey)?;
+            let salt = crypto::generate_salt();
+            let (key, verifier) = crypto::derive_key(&password, &salt)?;
+            revision.content = crypto::encrypt(&key, &revision.content)?;
+            revision.salt = Some(salt);
+            revision.verifier = Some(verifier);
+        }
+        Ok(revision)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NewEntry {
+    pub id: Option<String>,
+    pub title: Option<String>,
+    pub language: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub expires_in: Option<u64>,
+    pub expires_at: Option<u64>,
+    #[serde(default)]
+    pub burn_after_reading: bool,
+    pub max_reads: Option<u32>,
+    #[serde(default)]
+    pub visibility: Visibility,
+    #[serde(default)]
+    pub shared_with: Vec<String>,
+    #[serde(flatten)]
+    pub content: EntryContent,
+}
+
+impl NewEntry {
+    pub fn expires_at(&self, now: u64) -> Result<Option<u64>, Error> {
+        match (self.expires_in, self.expires_at) {
+            (Some(_), Some(_)) | (Some(0), None) => Err(Error::InvalidExpiry),
+            (Some(secs), None) => Ok(Some(now.saturating_add(secs))),
+            (None, Some(at)) if at <= now => Err(Error::InvalidExpiry),
+            (None, at) => Ok(at),
+        }
+    }
+
+    pub fn title(&self) -> Result<Option<String>, Error> {
+        let title = self.title.as_deref().map(str::trim).unwrap_or_default();
+        match title.chars().count() {
+            0 => Ok(None),
+            n if n > MAX_TITLE_LENGTH => Err(Error::TitleTooLong(MAX_TITLE_LENGTH)),
+            _ => Ok(Some(title.to_string())),
+        }
+    }
+
+    // A syntax hint, kept as given, e.g. `rust` or `c++`.
+    pub fn language(&self) -> Result<Option<String>, Error> {
+        let language = self.language.as_deref().map(str::trim).unwrap_or_default();
+        match language.chars().count() {
+            0 => Ok(None),
+            n if n > MAX_LANGUAGE_LENGTH => Err(Error::LanguageTooLong(MAX_LANGUAGE_LENGTH)),
+            _ => Ok(Some(language.to_string())),
+        }
+    }
+
+    // Duplicate tags are dropped.
+    pub fn tags(&self) -> Result<Vec<String>, Error> {
+        let mut tags: Vec<String> = Vec::new();
+        for tag in &self.tags {
+            let tag = normalize_tag(tag)?;
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+        if tags.len() > MAX_TAGS {
+            return Err(Error::TooManyTags(MAX_TAGS));
+        }
+        Ok(tags)
+    }
+
+    pub fn reads_left(&self) -> Result<Option<u32>, Error> {
+        match (self.max_reads, self.burn_after_reading) {
+            (Some(0), _) => Err(Error::InvalidReadLimit),
+            (Some(n), _) => Ok(Some(n)),
+            (None, true) => Ok(Some(1)),
+            (None, false) => Ok(None),
+        }
+    }
+}
+
+// Options for uploads that aren't JSON: the query string of a `text/plain`
+// body, or the fields of a multipart form.
+#[derive(Debug, FromForm)]
+pub struct AddOptions {
+    pub id: Option<String>,
+    pub title: Option<String>,
+    pub language: Option<String>,
+    pub tags: Vec<String>,
+    pub expires_in: Option<u64>,
+    pub expires_at: Option<u64>,
+    #[field(default = false)]
+    pub burn_after_reading: bool,
+    pub max_reads: Option<u32>,
+    pub filename: Option<String>,
+    pub visibility: Option<Visibility>,
+    pub shared_with: Vec<String>,
+}
+
+impl AddOptions {
+    pub fn into_entry(
+        self,
+        content: Vec<u8>,
+        key: Option<String>,
+        mime: Option<String>,
+    ) -> NewEntry {
+        NewEntry {
+            id: self.id,
+            title: self.title,
+            language: self.language,
+            tags: self.tags,
+            expires_in: self.expires_in,
+            expires_at: self.expires_at,
+            burn_after_reading: self.burn_after_reading,
+            max_reads: self.max_reads,
+            visibility: self.visibility.unwrap_or_default(),
+            shared_with: self.shared_with,
+            content: EntryContent {
+                content,
+                encrypted: key.is_some(),
+                key,
+                mime,
+                filename: self.filename,
+            },
+        }
+    }
+}
+
+#[derive(Debug, FromForm)]
+pub struct Upload<'r> {
+    pub content: Option<String>,
+    pub file: Option<Capped<TempFile<'r>>>,
+    pub key: Option<String>,
+    pub id: Option<String>,
+    pub title: Option<String>,
+    pub language: Option<String>,
+    pub tags: Vec<String>,
+    pub expires_in: Option<u64>,
+    pub expires_at: Option<u64>,
+    #[field(default = false)]
+    pub burn_after_reading: bool,
+    pub max_reads: Option<u32>,
+    pub filename: Option<String>,
+    pub visibility: Option<Visibility>,
+    pub shared_with: Vec<String>,
+}
+
+impl Upload<'_> {
+    pub fn options(&self) -> AddOptions {
+        let filename = self.filename.clone().or_else(|| {
+            let name = self.file.as_ref()?.raw_name()?;
+            Some(name.dangerous_unsafe_unsanitized_raw().to_string())
+        });
+        AddOptions {
+            id: self.id.clone(),
+            title: self.title.clone(),
+            language: self.language.clone(),
+            tags: self.tags.clone(),
+            expires_in: self.expires_in,
+            expires_at: self.expires_at,
+            burn_after_reading: self.burn_after_reading,
+            max_reads: self.max_reads,
+            filename,
+            visibility: self.visibility,
+            shared_with: self.shared_with.clone(),
+        }
+    }
+}
+
+// Keeps only the last path component and drops characters that would need
+// escaping in a Content-Disposition header.
+pub fn sanitize_filename(name: &str) -> String {
+    let name = name.rsplit(['/', '\\']).next().unwrap_or_default();
+    name.chars()
+        .filter(|c| !c.is_control() && !matches!(c, '"' | ';'))
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+// Tags are compared lowercased.
+pub fn normalize_tag(tag: &str) -> Result<String, Error> {
+    let tag = tag.trim().to_lowercase();
+    if !is_slug(&tag, MAX_TAG_LENGTH) {
+        return Err(Error::InvalidTag(MAX_TAG_LENGTH));
+    }
+    Ok(tag)
+}
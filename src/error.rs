@@ -0,0 +1,185 @@
+use rocket::http::Status;
+use rocket::request::Request;
+use rocket::response::{self, Responder};
+use rocket::serde::json::Json;
+use serde::Serialize;
+use thiserror::Error;
+
+#[derive(Error, Debug, Serialize)]
+pub enum Error {
+    #[error("key derivation failed")]
+    KeyDerivation,
+    #[error("encryption failed")]
+    Encryption,
+    #[error("decryption failed")]
+    Decryption,
+    #[error("encrypted entries need a key")]
+    MissingKey,
+    #[error("set either expires_in or a future expires_at")]
+    InvalidExpiry,
+    #[error("max_reads must be at least 1")]
+    InvalidReadLimit,
+    #[error("upload needs either a content or a file field")]
+    InvalidUpload,
+    #[error("content is not valid base64")]
+    InvalidEncoding,
+    #[error("ids are 1 to {0} letters, digits, '-' or '_'")]
+    InvalidId(usize),
+    #[error("key must be at most {0} bytes")]
+    KeyTooLong(usize),
+    #[error("content exceeds the limit of {0} bytes")]
+    ContentTooLarge(u64),
+    #[error("store is full")]
+    StoreFull,
+    #[error("too many requests, retry in {0} seconds")]
+    RateLimited(u64),
+    #[error("usernames are 1 to {0} letters, digits, '-' or '_'")]
+    InvalidUsername(usize),
+    #[error("password must be at least {0} characters")]
+    WeakPassword(usize),
+    #[error("password must be at most {0} characters")]
+    PasswordTooLong(usize),
+    #[error("username is taken")]
+    UsernameTaken,
+    #[error("wrong username or password")]
+    InvalidCredentials,
+    #[error("private entries need an owner, log in first")]
+    MissingOwner,
+    #[error("title must be at most {0} characters")]
+    TitleTooLong(usize),
+    #[error("language must be at most {0} characters")]
+    LanguageTooLong(usize),
+    #[error("tags are 1 to {0} letters, digits, '-' or '_'")]
+    InvalidTag(usize),
+    #[error("at most {0} tags are allowed")]
+    TooManyTags(usize),
+    #[error("invalid cursor")]
+    InvalidCursor,
+    #[error("search query has no words")]
+    EmptyQuery,
+    #[error("entry content is not text")]
+    BinaryContent,
+    #[error("entry is not encrypted")]
+    NotEncrypted,
+    #[error("entry is encrypted")]
+    Encrypted,
+    #[error("wrong key")]
+    WrongKey,
+    #[error("entry not found")]
+    NotFound,
+    #[error("invalid token")]
+    InvalidToken,
+    #[error("entry with same id already exists")]
+    DuplicateEntry,
+    #[error("no free id found, consider increasing id_length")]
+    IdSpaceExhausted,
+    #[error("storage error: {0}")]
+    Storage(String),
+    #[error("internal error")]
+    Internal,
+}
+
+impl Error {
+    pub fn status(&self) -> Status {
+        use Error::*;
+        match self {
+            MissingKey | InvalidExpiry | InvalidReadLimit | InvalidUpload | InvalidEncoding
+            | InvalidId(_) | KeyTooLong(_) | BinaryContent | NotEncrypted | InvalidUsername(_)
+            | WeakPassword(_) | PasswordTooLong(_) | MissingOwner | TitleTooLong(_)
+            | LanguageTooLong(_) | InvalidTag(_) | TooManyTags(_) | InvalidCursor | EmptyQuery => {
+                Status::BadRequest
+            }
+            InvalidCredentials => Status::Unauthorized,
+            ContentTooLarge(_) => Status::PayloadTooLarge,
+            Encrypted | WrongKey | InvalidToken => Status::Forbidden,
+            NotFound => Status::NotFound,
+            DuplicateEntry | UsernameTaken => Status::Conflict,
+            StoreFull => Status::InsufficientStorage,
+            RateLimited(_) => Status::TooManyRequests,
+            KeyDerivation | Encryption | Decryption | IdSpaceExhausted | Storage(_) | Internal => {
+                Status::InternalServerError
+            }
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        use Error::*;
+        match self {
+            KeyDerivation => "key_derivation",
+            Encryption => "encryption",
+            Decryption => "decryption",
+            MissingKey => "missing_key",
+            InvalidExpiry => "invalid_expiry",
+            InvalidReadLimit => "invalid_read_limit",
+            InvalidUpload => "invalid_upload",
+            InvalidEncoding => "invalid_encoding",
+            InvalidId(_) => "invalid_id",
+            KeyTooLong(_) => "key_too_long",
+            ContentTooLarge(_) => "content_too_large",
+            StoreFull => "store_full",
+            RateLimited(_) => "rate_limited",
+            InvalidUsername(_) => "invalid_username",
+            WeakPassword(_) => "weak_password",
+            PasswordTooLong(_) => "password_too_long",
+            UsernameTaken => "username_taken",
+            InvalidCredentials => "invalid_credentials",
+            MissingOwner => "missing_owner",
+            TitleTooLong(_) => "title_too_long",
+            LanguageTooLong(_) => "language_too_long",
+            InvalidTag(_) => "invalid_tag",
+            TooManyTags(_) => "too_many_tags",
+            InvalidCursor => "invalid_cursor",
+            EmptyQuery => "empty_query",
+            BinaryContent => "binary_content",
+            NotEncrypted => "not_encrypted",
+            Encrypted => "encrypted",
+            WrongKey => "wrong_key",
+            NotFound => "not_found",
+            InvalidToken => "invalid_token",
+            DuplicateEntry => "duplicate_entry",
+            IdSpaceExhausted => "id_space_exhausted",
+            Storage(_) => "storage",
+            Internal => "internal",
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ErrorBody {
+    code: String,
+    message: String,
+}
+
+impl<'r> Responder<'r, 'static> for Error {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        let status = self.status();
+        // internal details (paths, io errors) stay in the log
+        let message = if status == Status::InternalServerError {
+            error_!("{}", self);
+            String::from("internal server error")
+        } else {
+            self.to_string()
+        };
+        let body = ErrorBody {
+            code: self.code().to_string(),
+            message,
+        };
+        let mut response = (status, Json(body)).respond_to(req)?;
+        if let Error::RateLimited(secs) = self {
+            response.set_raw_header("Retry-After", secs.to_string());
+        }
+        Ok(response)
+    }
+}
+
+// Failures raised by Rocket itself (malformed JSON, missing guards, unknown
+// routes) get the same body shape as `Error`.
+#[catch(default)]
+pub fn default_catcher(status: Status, _: &Request) -> (Status, Json<ErrorBody>) {
+    let reason = status.reason().unwrap_or("error");
+    let body = ErrorBody {
+        code: reason.to_lowercase().replace([' ', '-'], "_"),
+        message: reason.to_lowercase(),
+    };
+    (status, Json(body))
+}
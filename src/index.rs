@@ -0,0 +1,211 @@
+use crate::entry::{Entry, Revision, Visibility};
+use crate::search::{Query, SearchIndex};
+use crate::storage::Storage;
+use crate::Error;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL, Engine};
+use std::collections::{BTreeSet, HashMap};
+use std::ops::Bound;
+
+// Position in the public listing, ordered by creation time and id.
+pub type ListingKey = (u64, String);
+
+// Keeps secondary indexes in step with the storage underneath, which can
+// only look entries up by id.
+pub struct Indexed {
+    storage: Box<dyn Storage>,
+    indexes: Indexes,
+    // Running totals for the quota, so writes don't have to walk every entry.
+    count: usize,
+    size: u64,
+}
+
+#[derive(Default)]
+struct Indexes {
+    public: BTreeSet<ListingKey>,
+    tags: HashMap<String, BTreeSet<ListingKey>>,
+    search: SearchIndex,
+}
+
+impl Indexed {
+    pub fn new(storage: Box<dyn Storage>) -> Indexed {
+        let mut indexes = Indexes::default();
+        let (mut count, mut size) = (0, 0);
+        for entry in storage.entries() {
+            indexes.add(entry);
+            count += 1;
+            size += entry.size();
+        }
+        Indexed {
+            storage,
+            indexes,
+            count,
+            size,
+        }
+    }
+
+    // Number of entries and their combined size, expired ones included.
+    pub fn totals(&self) -> (usize, u64) {
+        (self.count, self.size)
+    }
+
+    // Newest first, starting right after `cursor`.
+    pub fn public(&self, cursor: Option<&ListingKey>) -> impl Iterator<Item = &ListingKey> {
+        newest_first(&self.indexes.public, cursor)
+    }
+
+    pub fn tagged(
+        &self,
+        tag: &str,
+        cursor: Option<&ListingKey>,
+    ) -> impl Iterator<Item = &ListingKey> {
+        let cursor = cursor.cloned();
+        self.indexes
+            .tags
+            .get(tag)
+            .into_iter()
+            .flat_map(move |keys| newest_first(keys, cursor.as_ref()))
+    }
+
+    pub fn tags(&self) -> impl Iterator<Item = (&String, usize)> {
+        self.indexes
+            .tags
+            .iter()
+            .map(|(tag, keys)| (tag, keys.len()))
+    }
+
+    pub fn search(&self, query: &Query) -> Vec<(String, f64)> {
+        self.indexes.search.search(query)
+    }
+}
+
+impl Indexes {
+    // Only public entries are listed, and of those only the ones in plain
+    // text are searchable. Search snippets would give away entries with a
+    // read limit without counting the read, so those aren't searchable either.
+    fn add(&mut self, entry: &Entry) {
+        if entry.visibility != Visibility::Public {
+            return;
+        }
+        let key = listing_key(entry);
+        for tag in &entry.tags {
+            self.tags
+                .entry(tag.clone())
+                .or_default()
+                .insert(key.clone());
+        }
+        self.public.insert(key);
+        if entry.reads_left.is_some() {
+            return;
+        }
+        if let Some(text) = entry.current.text() {
+            self.search.insert(&entry.id, entry.title.as_deref(), text);
+        }
+    }
+
+    fn forget(&mut self, entry: &Entry) {
+        let key = listing_key(entry);
+        for tag in &entry.tags {
+            if let Some(keys) = self.tags.get_mut(tag) {
+                keys.remove(&key);
+                if keys.is_empty() {
+                    self.tags.remove(tag);
+                }
+            }
+        }
+        self.public.remove(&key);
+        self.search.remove(&entry.id);
+    }
+}
+
+fn newest_first<'a>(
+    keys: &'a BTreeSet<ListingKey>,
+    cursor: Option<&ListingKey>,
+) -> impl Iterator<Item = &'a ListingKey> + 'a {
+    let end = match cursor {
+        Some(cursor) => Bound::Excluded(cursor.clone()),
+        None => Bound::Unbounded,
+    };
+    keys.range((Bound::Unbounded, end)).rev()
+}
+
+pub fn listing_key(entry: &Entry) -> ListingKey {
+    (entry.created_at(), entry.id.clone())
+}
+
+// Cursors are opaque to clients, they only pass back what they were given.
+pub fn encode_cursor((created_at, id): &ListingKey) -> String {
+    BASE64_URL.encode(format!("{}.{}", created_at, id))
+}
+
+pub fn decode_cursor(cursor: &str) -> Result<ListingKey, Error> {
+    let decoded = BASE64_URL
+        .decode(cursor)
+        .map_err(|_| Error::InvalidCursor)?;
+    let decoded = String::from_utf8(decoded).map_err(|_| Error::InvalidCursor)?;
+    let (created_at, id) = decoded.split_once('.').ok_or(Error::InvalidCursor)?;
+    let created_at = created_at.parse().map_err(|_| Error::InvalidCursor)?;
+    Ok((created_at, id.to_string()))
+}
+
+impl Storage for Indexed {
+    fn get(&self, id: &str) -> Option<Entry> {
+        self.storage.get(id)
+    }
+
+    fn get_ref(&self, id: &str) -> Option<&Entry> {
+        self.storage.get_ref(id)
+    }
+
+    fn contains(&self, id: &str) -> bool {
+        self.storage.contains(id)
+    }
+
+    fn insert(&mut self, entry: Entry) -> Result<(), Error> {
+        self.storage.insert(entry.clone())?;
+        self.indexes.add(&entry);
+        self.count += 1;
+        self.size += entry.size();
+        Ok(())
+    }
+
+    fn replace(&mut self, entry: Entry) -> Result<Entry, Error> {
+        let old = self.storage.replace(entry.clone())?;
+        self.indexes.forget(&old);
+        self.indexes.add(&entry);
+        self.size = self.size - old.size() + entry.size();
+        Ok(old)
+    }
+
+    fn remove(&mut self, id: &str) -> Result<Option<Entry>, Error> {
+        let old = self.storage.remove(id)?;
+        if let Some(old) = &old {
+            self.indexes.forget(old);
+            self.count -= 1;
+            self.size -= old.size();
+        }
+        Ok(old)
+    }
+
+    fn push_revision(&mut self, id: &str, revision: Revision) -> Result<(), Error> {
+        let old = self.storage.get(id).ok_or(Error::NotFound)?;
+        self.storage.push_revision(id, revision)?;
+        self.indexes.forget(&old);
+        if let Some(entry) = self.storage.get(id) {
+            self.indexes.add(&entry);
+            self.size = self.size - old.size() + entry.size();
+        }
+        Ok(())
+    }
+
+    fn count_view(&mut self, id: &str) -> Result<(), Error> {
+        self.storage.count_view(id)
+    }
+
+    fn flush_views(&mut self) -> Result<(), Error> {
+        self.storage.flush_views()
+    }
+
+    fn entries(&self) -> Box<dyn Iterator<Item = &Entry> + '_> {
+        self.storage.entries()
+    }
+}
@@ -1,53 +1,247 @@
+use accounts::{Accounts, MaybeUser, User};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use clipboard::{Access, Clipboard, Quota};
+use entry::{AddOptions, Entry, EntryContent, NewEntry, Upload, Visibility};
+use error::Error;
+use index::ListingKey;
+use page::Highlighter;
+use rand::rngs::OsRng;
+use rand::seq::SliceRandom;
+use ratelimit::{AddLimit, DecryptLimit, LoginLimit, RateLimiter, UpdateLimit};
+use rocket::data::{ByteUnit, Data};
+use rocket::fairing::AdHoc;
+use rocket::form::{Form, ValueField};
 use rocket::fs::FileServer;
-use rocket::State;
+use rocket::http::{ContentType, RawStr};
+use rocket::request::{self, FromRequest, Request};
+use rocket::tokio::io::AsyncReadExt;
 use rocket::{http::Status, serde::json::Json};
+use rocket::{Build, Rocket, State};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
-use std::{thread, time};
-use thiserror::Error;
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+mod accounts;
+mod clipboard;
+mod crypto;
+mod diff;
+mod entry;
+mod error;
+mod index;
+mod page;
+mod ratelimit;
+mod raw;
+mod search;
+mod storage;
 
 #[macro_use]
 extern crate rocket;
 
-struct Clipboard {
-    entries: Arc<Mutex<HashMap<String, Entry>>>,
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
 }
 
-impl Clipboard {
-    fn init() -> Clipboard {
-        Clipboard {
-            entries: Arc::new(Mutex::new(HashMap::new())),
-        }
+// Argon2 and diffing take long enough to stall every other request on the
+// same worker, so they run on the blocking pool.
+async fn blocking<T: Send + 'static>(
+    f: impl FnOnce() -> Result<T, Error> + Send + 'static,
+) -> Result<T, Error> {
+    rocket::tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|_| Error::Internal)?
+}
+
+// Ids and usernames end up in urls, so they are kept to url safe characters.
+fn is_slug(s: &str, max_len: usize) -> bool {
+    (1..=max_len).contains(&s.len())
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+#[derive(Debug, Deserialize)]
+struct Config {
+    storage: Option<PathBuf>,
+    accounts: Option<PathBuf>,
+    #[serde(default = "default_id_length")]
+    id_length: usize,
+    #[serde(default = "default_id_alphabet")]
+    id_alphabet: String,
+    #[serde(default = "default_reap_interval")]
+    reap_interval: u64,
+    #[serde(default = "default_max_id_length")]
+    max_id_length: usize,
+    #[serde(default = "default_max_key_length")]
+    max_key_length: usize,
+    // Plaintext size of one revision. Rocket's own request `limits` cut off
+    // bodies before this, so they have to be at least as large.
+    #[serde(default = "default_max_content_size")]
+    max_content_size: ByteUnit,
+    max_entries: Option<usize>,
+    max_store_size: Option<ByteUnit>,
+    // Keyed by route: "add", "update", "decrypt" or "login". A route without
+    // an entry isn't limited. Configuring any of them replaces all the
+    // defaults, so the others have to be listed too or they go unlimited.
+    #[serde(default = "ratelimit::default_limits")]
+    rate_limits: HashMap<String, ratelimit::Limit>,
+    #[serde(default)]
+    decrypt_lockout: ratelimit::Lockout,
+    #[serde(default)]
+    trusted_proxies: Vec<IpAddr>,
+}
+
+fn default_id_length() -> usize {
+    12
+}
+
+fn default_id_alphabet() -> String {
+    String::from("ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789")
+}
+
+fn default_reap_interval() -> u64 {
+    60
+}
+
+fn default_max_id_length() -> usize {
+    64
+}
+
+fn default_max_key_length() -> usize {
+    1024
+}
+
+fn default_max_content_size() -> ByteUnit {
+    ByteUnit::Mebibyte(1)
+}
+
+impl Config {
+    fn generate_id(&self) -> String {
+        let alphabet: Vec<char> = self.id_alphabet.chars().collect();
+        (0..self.id_length)
+            .map(|_| *alphabet.choose(&mut OsRng).unwrap())
+            .collect()
     }
 
-    fn add(&self, entry: Entry) -> Result<(), Error> {
-        let ret = self.entries.lock().unwrap().insert(entry.id.clone(), entry);
-        match ret {
-            Some(_) => Err(Error::DuplicateEntry),
-            None => Ok(()),
+    fn check_id(&self, id: &str) -> Result<(), Error> {
+        match is_slug(id, self.max_id_length) {
+            true => Ok(()),
+            false => Err(Error::InvalidId(self.max_id_length)),
         }
     }
 
-    fn get(&self, id: &str) -> Option<Entry> {
-        self.entries.lock().unwrap().get(id).cloned()
+    fn check_content(&self, content: &EntryContent) -> Result<(), Error> {
+        if content.content.len() as u64 > self.max_content_size.as_u64() {
+            return Err(Error::ContentTooLarge(self.max_content_size.as_u64()));
+        }
+        match &content.key {
+            Some(key) if key.len() > self.max_key_length => {
+                Err(Error::KeyTooLong(self.max_key_length))
+            }
+            _ => Ok(()),
+        }
     }
-}
 
-#[derive(Error, Debug, Serialize)]
-enum Error {
-    #[error("key len {key_len} != data len {data_len}")]
-    PadDiffLength { key_len: usize, data_len: usize },
-    #[error("entry with same id already exists")]
-    DuplicateEntry,
+    fn quota(&self) -> Quota {
+        Quota {
+            max_entries: self.max_entries,
+            max_size: self.max_store_size.map(ByteUnit::as_u64),
+        }
+    }
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
-struct Entry {
+// Text is returned as is, binary and encrypted content base64 encoded.
+#[derive(Debug, Serialize)]
+struct EntryView {
     id: String,
+    revision: usize,
     content: String,
+    encoding: &'static str,
     encrypted: bool,
-    key: Option<String>,
+    mime: Option<String>,
+    filename: Option<String>,
+    visibility: Visibility,
+    title: Option<String>,
+    language: Option<String>,
+    tags: Vec<String>,
+    created_at: u64,
+    updated_at: u64,
+    size: u64,
+    views: u64,
+    reads_left: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+struct RevisionInfo {
+    revision: usize,
+    created_at: u64,
+    encrypted: bool,
+    size: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct UpdateResponse {
+    revision: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct AddResponse {
+    id: String,
+    edit_token: String,
+    delete_token: String,
+}
+
+struct EditAccess(Access);
+struct DeleteAccess(Access);
+
+// The entry's token header wins over a bearer token, so a logged in user can
+// still edit anonymous pastes they hold the token for.
+fn access(req: &Request<'_>, name: &str) -> request::Outcome<Access, ()> {
+    if let Some(token) = req.headers().get_one(name) {
+        return request::Outcome::Success(Access::Token(token.to_string()));
+    }
+    accounts::bearer(req).and_then(|user| match user {
+        Some(user) => request::Outcome::Success(Access::Owner(user.username)),
+        None => request::Outcome::Error((Status::Unauthorized, ())),
+    })
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for EditAccess {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        access(req, "X-Edit-Token").map(EditAccess)
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for DeleteAccess {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        access(req, "X-Delete-Token").map(DeleteAccess)
+    }
+}
+
+// Keeps the key of a `text/plain` upload out of the query string, which ends
+// up in access logs.
+struct EncryptionKey(Option<String>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for EncryptionKey {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let key = req
+            .headers()
+            .get_one("X-Encryption-Key")
+            .map(str::to_string);
+        request::Outcome::Success(EncryptionKey(key))
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -55,121 +249,1780 @@ struct DecryptRequest {
     key: String,
 }
 
-fn pad(key: &str, data: &str) -> Result<String, Error> {
-    use Error::*;
-    if key.len() != data.len() {
-        return Err(PadDiffLength {
-            key_len: key.len(),
-            data_len: data.len(),
-        });
+#[derive(Debug, Deserialize)]
+struct VisibilityRequest {
+    visibility: Visibility,
+    #[serde(default)]
+    shared_with: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Credentials {
+    username: String,
+    password: String,
+}
+
+#[derive(Debug, Serialize)]
+struct AccountView {
+    username: String,
+    created_at: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct LoginResponse {
+    token: String,
+}
+
+// Listing metadata, never the content itself.
+#[derive(Debug, Serialize)]
+struct EntrySummary {
+    id: String,
+    title: Option<String>,
+    language: Option<String>,
+    tags: Vec<String>,
+    revisions: usize,
+    created_at: u64,
+    updated_at: u64,
+    size: u64,
+    encrypted: bool,
+    visibility: Visibility,
+    views: u64,
+    expires_at: Option<u64>,
+    reads_left: Option<u32>,
+}
+
+impl From<&Entry> for EntrySummary {
+    fn from(entry: &Entry) -> EntrySummary {
+        EntrySummary {
+            id: entry.id.clone(),
+            title: entry.title.clone(),
+            language: entry.language.clone(),
+            tags: entry.tags.clone(),
+            revisions: entry.revision_number(),
+            created_at: entry.created_at(),
+            updated_at: entry.current.created_at,
+            size: entry.current.content.len() as u64,
+            encrypted: entry.current.encrypted,
+            visibility: entry.visibility,
+            views: entry.views,
+            expires_at: entry.expires_at,
+            reads_left: entry.reads_left,
+        }
     }
+}
+
+const DEFAULT_PAGE_SIZE: usize = 20;
+const MAX_PAGE_SIZE: usize = 100;
 
-    let data_chars: Vec<char> = data.chars().collect();
-    let key_chars: Vec<char> = key.chars().collect();
+#[derive(Debug, Serialize)]
+struct ListedEntry {
+    id: String,
+    title: Option<String>,
+    tags: Vec<String>,
+    created_at: u64,
+    size: u64,
+    encrypted: bool,
+}
 
-    let mut out = String::new();
-    for i in 0..key_chars.len() {
-        let out_char = char::from_u32(u32::from(data_chars[i]) ^ u32::from(key_chars[i]));
-        out.push(out_char.unwrap());
+#[derive(Debug, Serialize)]
+struct EntryPage {
+    entries: Vec<ListedEntry>,
+    next_cursor: Option<String>,
+}
+
+impl From<&Entry> for ListedEntry {
+    fn from(entry: &Entry) -> ListedEntry {
+        ListedEntry {
+            id: entry.id.clone(),
+            title: entry.title.clone(),
+            tags: entry.tags.clone(),
+            created_at: entry.created_at(),
+            size: entry.current.content.len() as u64,
+            encrypted: entry.current.encrypted,
+        }
     }
-    Ok(out)
 }
 
-fn not_so_constant_time_strcmp(a: &str, b: &str) -> bool {
-    if a.len() != b.len() {
-        return false;
+impl EntryPage {
+    fn new(entries: Vec<ListedEntry>, next: Option<ListingKey>) -> EntryPage {
+        EntryPage {
+            entries,
+            next_cursor: next.as_ref().map(index::encode_cursor),
+        }
     }
+}
 
-    let a: Vec<char> = a.chars().collect();
-    let b: Vec<char> = b.chars().collect();
+#[get("/entries?<cursor>&<limit>")]
+fn list_entries(
+    cursor: Option<String>,
+    limit: Option<usize>,
+    data: &State<Clipboard>,
+) -> Result<Json<EntryPage>, Error> {
+    let cursor = cursor.as_deref().map(index::decode_cursor).transpose()?;
+    let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+    let (entries, next) =
+        data.public_entries(cursor.as_ref(), limit, |entry| ListedEntry::from(entry));
+    Ok(Json(EntryPage::new(entries, next)))
+}
 
-    for i in 0..a.len() {
-        thread::sleep(time::Duration::from_millis(100));
-        if a[i] != b[i] {
-            return false;
-        }
+#[derive(Debug, Serialize)]
+struct TagCount {
+    tag: String,
+    count: usize,
+}
+
+#[get("/tags?<limit>")]
+fn list_tags(limit: Option<usize>, data: &State<Clipboard>) -> Json<Vec<TagCount>> {
+    let tags = data
+        .tag_counts()
+        .into_iter()
+        .take(limit.unwrap_or(usize::MAX))
+        .map(|(tag, count)| TagCount { tag, count })
+        .collect();
+    Json(tags)
+}
+
+#[get("/tags/<tag>?<cursor>&<limit>")]
+fn tagged_entries(
+    tag: &str,
+    cursor: Option<String>,
+    limit: Option<usize>,
+    data: &State<Clipboard>,
+) -> Result<Json<EntryPage>, Error> {
+    let tag = entry::normalize_tag(tag)?;
+    let cursor = cursor.as_deref().map(index::decode_cursor).transpose()?;
+    let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+    let (entries, next) = data.tagged(&tag, cursor.as_ref(), limit, |entry| {
+        ListedEntry::from(entry)
+    });
+    Ok(Json(EntryPage::new(entries, next)))
+}
+
+#[derive(Debug, Serialize)]
+struct SearchResult {
+    id: String,
+    title: Option<String>,
+    created_at: u64,
+    size: u64,
+    score: f64,
+    snippet: Option<String>,
+}
+
+#[get("/search?<q>&<limit>")]
+fn search_entries(
+    q: String,
+    limit: Option<usize>,
+    data: &State<Clipboard>,
+) -> Result<Json<Vec<SearchResult>>, Error> {
+    let query = search::Query::parse(&q);
+    if query.is_empty() {
+        return Err(Error::EmptyQuery);
     }
-    true
+    let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+
+    let results = data.search(&query, limit, |entry, score| SearchResult {
+        id: entry.id.clone(),
+        title: entry.title.clone(),
+        created_at: entry.created_at(),
+        size: entry.current.content.len() as u64,
+        score,
+        snippet: entry.current.text().and_then(|text| query.snippet(text)),
+    });
+    Ok(Json(results))
 }
 
 #[get("/get?<id>")]
-fn get_entry(id: String, data: &State<Clipboard>) -> Result<Json<Entry>, Status> {
-    let entry = data.get(&id);
-
-    if let Some(entry) = entry {
-        Ok(Json(Entry {
-            id: entry.id,
-            content: entry.content,
-            encrypted: entry.encrypted,
-            key: None,
-        }))
+fn get_entry(
+    id: String,
+    user: MaybeUser,
+    data: &State<Clipboard>,
+) -> Result<Json<EntryView>, Error> {
+    view_entry(&id, None, &user, data)
+}
+
+#[get("/entry/<id>?<rev>")]
+fn get_revision(
+    id: String,
+    rev: Option<usize>,
+    user: MaybeUser,
+    data: &State<Clipboard>,
+) -> Result<Json<EntryView>, Error> {
+    view_entry(&id, rev, &user, data)
+}
+
+fn view_entry(
+    id: &str,
+    rev: Option<usize>,
+    user: &MaybeUser,
+    data: &Clipboard,
+) -> Result<Json<EntryView>, Error> {
+    let (entry, rev) = data.read(id, rev, user.username())?;
+    let revision = entry.revision(rev).ok_or(Error::NotFound)?;
+
+    let (content, encoding) = match revision.text() {
+        Some(text) => (text.to_string(), "utf8"),
+        None => (BASE64.encode(&revision.content), "base64"),
+    };
+
+    Ok(Json(EntryView {
+        id: entry.id.clone(),
+        revision: rev,
+        content,
+        encoding,
+        encrypted: revision.encrypted,
+        mime: revision.mime.clone(),
+        filename: revision.filename.clone(),
+        visibility: entry.visibility,
+        title: entry.title.clone(),
+        language: entry.language.clone(),
+        tags: entry.tags.clone(),
+        created_at: entry.created_at(),
+        updated_at: entry.current.created_at,
+        size: revision.content.len() as u64,
+        views: entry.views,
+        reads_left: entry.reads_left,
+    }))
+}
+
+#[get("/entry/<id>/revisions")]
+fn list_revisions(
+    id: String,
+    user: MaybeUser,
+    data: &State<Clipboard>,
+) -> Result<Json<Vec<RevisionInfo>>, Error> {
+    let entry = data
+        .get_visible(&id, user.username())
+        .ok_or(Error::NotFound)?;
+    let revisions = entry
+        .revisions()
+        .enumerate()
+        .map(|(i, revision)| RevisionInfo {
+            revision: i + 1,
+            created_at: revision.created_at,
+            encrypted: revision.encrypted,
+            size: revision.content.len(),
+        })
+        .collect();
+    Ok(Json(revisions))
+}
+
+// Without `b` this diffs two revisions of `a`, by default the latest edit.
+#[get("/diff?<a>&<b>&<a_rev>&<b_rev>")]
+async fn diff_entries(
+    a: String,
+    b: Option<String>,
+    a_rev: Option<usize>,
+    b_rev: Option<usize>,
+    user: MaybeUser,
+    data: &State<Clipboard>,
+) -> Result<Json<diff::Diff>, Error> {
+    let b = b.unwrap_or_else(|| a.clone());
+    let viewer = user.username();
+    let old_entry = data.get_visible(&a, viewer).ok_or(Error::NotFound)?;
+    let new_entry = if a == b {
+        old_entry.clone()
     } else {
-        Err(Status::NotFound)
+        data.get_visible(&b, viewer).ok_or(Error::NotFound)?
+    };
+
+    let b_rev = b_rev.unwrap_or(new_entry.revision_number());
+    let a_rev = match a_rev {
+        Some(rev) => rev,
+        None if a == b => b_rev.saturating_sub(1).max(1),
+        None => old_entry.revision_number(),
+    };
+    let old = old_entry.revision(a_rev).ok_or(Error::NotFound)?.clone();
+    let new = new_entry.revision(b_rev).ok_or(Error::NotFound)?.clone();
+    if old.encrypted || new.encrypted {
+        return Err(Error::Encrypted);
+    }
+    if old.text().is_none() || new.text().is_none() {
+        return Err(Error::BinaryContent);
+    }
+
+    data.consume(&a)?;
+    if a != b {
+        data.consume(&b)?;
     }
+    let diff = blocking(move || {
+        Ok(diff::diff(
+            &format!("{}@{}", a, a_rev),
+            old.text().unwrap_or_default(),
+            &format!("{}@{}", b, b_rev),
+            new.text().unwrap_or_default(),
+        ))
+    })
+    .await?;
+    Ok(Json(diff))
 }
 
-#[post("/add", data = "<entry>")]
-fn add_entry(mut entry: Json<Entry>, data: &State<Clipboard>) -> Status {
-    if entry.encrypted {
-        if let Some(key) = &entry.key {
-            if let Ok(ct) = pad(key, &entry.content) {
-                entry.content = ct;
-            } else {
-                return Status::InternalServerError;
+#[post("/add", format = "json", data = "<entry>")]
+async fn add_entry(
+    _limit: AddLimit,
+    user: MaybeUser,
+    entry: Json<NewEntry>,
+    data: &State<Clipboard>,
+    config: &State<Config>,
+) -> Result<Json<AddResponse>, Error> {
+    create_entry(entry.into_inner(), user, data, config).await
+}
+
+#[post("/add", format = "multipart/form-data", data = "<upload>", rank = 2)]
+async fn add_upload(
+    _limit: AddLimit,
+    user: MaybeUser,
+    upload: Form<Upload<'_>>,
+    data: &State<Clipboard>,
+    config: &State<Config>,
+) -> Result<Json<AddResponse>, Error> {
+    let mut upload = upload.into_inner();
+    let (content, mime) = match (upload.content.take(), &upload.file) {
+        (Some(content), None) => (content.into_bytes(), None),
+        (None, Some(file)) => {
+            if !file.is_complete() {
+                return Err(Error::ContentTooLarge(config.max_content_size.as_u64()));
             }
+            let mut content = Vec::new();
+            let mut reader = file.open().await.map_err(|_| Error::InvalidUpload)?;
+            reader
+                .read_to_end(&mut content)
+                .await
+                .map_err(|_| Error::InvalidUpload)?;
+            (content, file.content_type().and_then(upload_mime))
         }
+        _ => return Err(Error::InvalidUpload),
+    };
+    let entry = upload
+        .options()
+        .into_entry(content, upload.key.take(), mime);
+    create_entry(entry, user, data, config).await
+}
+
+// Any other body is taken verbatim. A urlencoded body is only read as a form
+// when it has a `content` field like an HTML form would send, so
+// `curl --data-binary @file` still works with curl's default form content
+// type. The body is read up to `max_content_size` rather than one of Rocket's
+// limits.
+#[post("/add?<options..>", data = "<content>", rank = 3)]
+#[allow(clippy::too_many_arguments)]
+async fn add_text(
+    _limit: AddLimit,
+    user: MaybeUser,
+    content: Data<'_>,
+    content_type: Option<&ContentType>,
+    options: AddOptions,
+    key: EncryptionKey,
+    data: &State<Clipboard>,
+    config: &State<Config>,
+) -> Result<Json<AddResponse>, Error> {
+    let limit = config.max_content_size;
+    let content = content
+        .open(limit)
+        .into_bytes()
+        .await
+        .map_err(|_| Error::InvalidUpload)?;
+    if !content.is_complete() {
+        return Err(Error::ContentTooLarge(limit.as_u64()));
     }
-    let res = data.add(entry.into_inner());
+    let content = content.into_inner();
+    if content_type.is_some_and(|content_type| content_type.is_form()) {
+        if let Some(entry) = form_entry(&content) {
+            return create_entry(entry, user, data, config).await;
+        }
+    }
+    let mime = content_type.and_then(upload_mime);
+    let entry = options.into_entry(content, key.0, mime);
+    create_entry(entry, user, data, config).await
+}
 
-    if res.is_ok() {
-        Status::Ok
-    } else {
-        Status::InternalServerError
+fn form_entry(body: &[u8]) -> Option<NewEntry> {
+    let body = std::str::from_utf8(body).ok()?;
+    let decode = |s: &str| RawStr::new(s).url_decode_lossy().into_owned();
+    let fields: Vec<(String, String)> = Form::values(body)
+        .map(|field| (decode(field.name.source()), decode(field.value)))
+        .collect();
+    let fields = fields
+        .iter()
+        .map(|(name, value)| ValueField::from((name.as_str(), value.as_str())));
+    let mut upload = Form::<Upload<'_>>::parse_iter(fields).ok()?;
+    let content = upload.content.take()?;
+    let options = upload.options();
+    Some(options.into_entry(content.into_bytes(), upload.key.take(), None))
+}
+
+// Plain text and form types say nothing about the content, those are left to
+// be detected from the bytes.
+fn upload_mime(content_type: &ContentType) -> Option<String> {
+    let generic = content_type.is_plain()
+        || *content_type == ContentType::Form
+        || *content_type == ContentType::Binary;
+    (!generic).then(|| content_type.to_string())
+}
+
+async fn create_entry(
+    entry: NewEntry,
+    user: MaybeUser,
+    data: &Clipboard,
+    config: &Config,
+) -> Result<Json<AddResponse>, Error> {
+    if let Some(id) = &entry.id {
+        config.check_id(id)?;
     }
+    config.check_content(&entry.content)?;
+    let title = entry.title()?;
+    let language = entry.language()?;
+    let tags = entry.tags()?;
+    let owner = user.0.map(|user| user.username);
+    if entry.visibility == Visibility::Private && owner.is_none() {
+        return Err(Error::MissingOwner);
+    }
+    let expires_at = entry.expires_at(now())?;
+    let reads_left = entry.reads_left()?;
+    let content = entry.content;
+    let mut stored = Entry::new(String::new(), blocking(move || content.seal()).await?);
+    stored.title = title;
+    stored.language = language;
+    stored.tags = tags;
+    stored.expires_at = expires_at;
+    stored.reads_left = reads_left;
+    let edit_token = crypto::generate_token();
+    let delete_token = crypto::generate_token();
+    stored.edit_token = Some(crypto::hash_token(&edit_token));
+    stored.delete_token = Some(crypto::hash_token(&delete_token));
+    stored.owner = owner;
+    stored.visibility = entry.visibility;
+    stored.shared_with = entry.shared_with;
+
+    let id = match entry.id {
+        Some(id) => {
+            stored.id = id.clone();
+            data.add(stored)?;
+            id
+        }
+        None => data.add_with_generated_id(stored, config)?,
+    };
+
+    Ok(Json(AddResponse {
+        id,
+        edit_token,
+        delete_token,
+    }))
+}
+
+#[put("/entry/<id>", data = "<content>")]
+async fn update_entry(
+    id: String,
+    content: Json<EntryContent>,
+    _limit: UpdateLimit,
+    access: EditAccess,
+    data: &State<Clipboard>,
+    config: &State<Config>,
+) -> Result<Json<UpdateResponse>, Error> {
+    config.check_content(&content)?;
+    data.check_edit(&id, &access.0)?;
+    let content = content.into_inner();
+    let revision = blocking(move || content.seal()).await?;
+    let revision = data.update(&id, revision, access.0)?;
+    Ok(Json(UpdateResponse { revision }))
+}
+
+#[put("/entry/<id>/visibility", data = "<request>")]
+fn update_visibility(
+    id: String,
+    request: Json<VisibilityRequest>,
+    access: EditAccess,
+    data: &State<Clipboard>,
+) -> Result<(), Error> {
+    let request = request.into_inner();
+    data.set_visibility(&id, request.visibility, request.shared_with, access.0)
+}
+
+#[delete("/entry/<id>")]
+fn delete_entry(id: String, access: DeleteAccess, data: &State<Clipboard>) -> Result<(), Error> {
+    data.delete(&id, access.0)
+}
+
+#[post("/register", data = "<credentials>")]
+async fn register(
+    _limit: LoginLimit,
+    credentials: Json<Credentials>,
+    accounts: &State<Accounts>,
+) -> Result<Json<AccountView>, Error> {
+    let (accounts, credentials) = (accounts.inner().clone(), credentials.into_inner());
+    let account =
+        blocking(move || accounts.register(&credentials.username, &credentials.password)).await?;
+    Ok(Json(AccountView {
+        username: account.username,
+        created_at: account.created_at,
+    }))
+}
+
+#[post("/login", data = "<credentials>")]
+async fn login(
+    _limit: LoginLimit,
+    credentials: Json<Credentials>,
+    accounts: &State<Accounts>,
+) -> Result<Json<LoginResponse>, Error> {
+    let (accounts, credentials) = (accounts.inner().clone(), credentials.into_inner());
+    let token =
+        blocking(move || accounts.login(&credentials.username, &credentials.password)).await?;
+    Ok(Json(LoginResponse { token }))
+}
+
+#[post("/logout")]
+fn logout(user: User, accounts: &State<Accounts>) -> Result<(), Error> {
+    accounts.logout(&user.token)
+}
+
+#[get("/me/entries")]
+fn my_entries(user: User, data: &State<Clipboard>) -> Json<Vec<EntrySummary>> {
+    Json(data.owned_by(&user.username, |entry| EntrySummary::from(entry)))
 }
 
-#[post("/decrypt?<id>", data = "<request>")]
-fn decrypt(
+// Decrypted content is served like `/raw`, the uploader's content type must
+// not run scripts on our origin either.
+#[post("/decrypt?<id>&<rev>", format = "json", data = "<request>")]
+#[allow(clippy::too_many_arguments)]
+async fn decrypt(
     id: String,
+    rev: Option<usize>,
     request: Json<DecryptRequest>,
+    _limit: DecryptLimit,
+    user: MaybeUser,
+    limiter: &State<RateLimiter>,
     data: &State<Clipboard>,
-) -> Result<String, Status> {
-    if let Some(entry) = data.get(&id) {
-        let key = match &entry.key {
-            Some(key) => key,
-            None => return Err(Status::InternalServerError),
-        };
-        if not_so_constant_time_strcmp(&request.key, key) {
-            if let Ok(pt) = pad(&request.key, &entry.content) {
-                Ok(pt)
-            } else {
-                Err(Status::InternalServerError)
-            }
-        } else {
-            Err(Status::InternalServerError)
-        }
-    } else {
-        Err(Status::NotFound)
+    config: &State<Config>,
+) -> Result<raw::Raw, Error> {
+    // checked before anything else, key derivation is expensive
+    limiter.check_lockout(&id)?;
+    if request.key.len() > config.max_key_length {
+        return Err(Error::KeyTooLong(config.max_key_length));
     }
+    let entry = data
+        .get_visible(&id, user.username())
+        .ok_or(Error::NotFound)?;
+    let revision = match rev {
+        Some(rev) => entry.revision(rev).ok_or(Error::NotFound)?,
+        None => &entry.current,
+    };
+    let (salt, expected) = match (&revision.salt, &revision.verifier) {
+        (Some(salt), Some(verifier)) => (salt, verifier),
+        _ => return Err(Error::NotEncrypted),
+    };
+
+    let (password, salt) = (request.into_inner().key, salt.clone());
+    let (key, verifier) = blocking(move || crypto::derive_key(&password, &salt)).await?;
+    if !crypto::verify(expected, &verifier) {
+        limiter.record_failure(&id);
+        return Err(Error::WrongKey);
+    }
+    limiter.clear_failures(&id);
+    let pt = crypto::decrypt(&key, &revision.content)?;
+    data.consume(&id)?;
+    let content_type = match (&revision.mime, std::str::from_utf8(&pt)) {
+        (Some(mime), _) => ContentType::parse_flexible(mime).unwrap_or(ContentType::Binary),
+        (None, Ok(_)) => ContentType::Plain,
+        (None, Err(_)) => ContentType::Binary,
+    };
+    Ok(raw::Raw::Content {
+        etag: raw::etag(&pt),
+        body: pt,
+        content_type,
+        disposition: None,
+        cache_control: "no-store",
+    })
+}
+
+// Pastes can be edited or deleted at any time, so caches have to revalidate
+// through the ETag. Entries with a read limit or expiry must not be cached at
+// all, a cached copy would outlive them, and neither must private ones.
+fn cacheable(entry: &Entry) -> bool {
+    entry.reads_left.is_none()
+        && entry.expires_at.is_none()
+        && entry.visibility != Visibility::Private
+}
+
+#[get("/raw/<id>?<rev>")]
+fn raw_entry(
+    id: String,
+    rev: Option<usize>,
+    if_none_match: raw::IfNoneMatch,
+    user: MaybeUser,
+    data: &State<Clipboard>,
+) -> Result<raw::Raw, Error> {
+    serve_raw(&id, rev, false, if_none_match, &user, data)
+}
+
+#[get("/download/<id>?<rev>")]
+fn download_entry(
+    id: String,
+    rev: Option<usize>,
+    if_none_match: raw::IfNoneMatch,
+    user: MaybeUser,
+    data: &State<Clipboard>,
+) -> Result<raw::Raw, Error> {
+    serve_raw(&id, rev, true, if_none_match, &user, data)
+}
+
+fn serve_raw(
+    id: &str,
+    rev: Option<usize>,
+    download: bool,
+    if_none_match: raw::IfNoneMatch,
+    user: &MaybeUser,
+    data: &Clipboard,
+) -> Result<raw::Raw, Error> {
+    let entry = data
+        .get_visible(id, user.username())
+        .ok_or(Error::NotFound)?;
+    let rev = rev.unwrap_or(entry.revision_number());
+    if entry.revision(rev).ok_or(Error::NotFound)?.encrypted {
+        return Err(Error::Encrypted);
+    }
+
+    let (entry, rev) = data.read(id, Some(rev), user.username())?;
+    let revision = entry.revision(rev).ok_or(Error::NotFound)?;
+    let etag = raw::etag(&revision.content);
+    let cacheable = cacheable(&entry);
+    if cacheable && if_none_match.0.as_deref() == Some(etag.as_str()) {
+        return Ok(raw::Raw::NotModified { etag });
+    }
+
+    let disposition = download.then(|| {
+        let filename = match (&revision.filename, revision.text()) {
+            (Some(filename), _) if !filename.is_empty() => filename.clone(),
+            (_, Some(_)) => format!("{}.txt", entry.id),
+            (_, None) => entry.id.clone(),
+        };
+        raw::attachment(&filename)
+    });
+
+    Ok(raw::Raw::Content {
+        body: revision.content.clone(),
+        content_type: revision.content_type(),
+        disposition,
+        etag,
+        cache_control: if cacheable { "no-cache" } else { "no-store" },
+    })
+}
+
+// The same entry as `/raw`, rendered as a highlighted HTML page.
+#[get("/p/<id>?<rev>")]
+fn view_page(
+    id: String,
+    rev: Option<usize>,
+    user: MaybeUser,
+    data: &State<Clipboard>,
+    highlighter: &State<Highlighter>,
+) -> Result<page::Page, Error> {
+    let entry = data
+        .get_visible(&id, user.username())
+        .ok_or(Error::NotFound)?;
+    let rev = rev.unwrap_or(entry.revision_number());
+    let revision = entry.revision(rev).ok_or(Error::NotFound)?;
+    if revision.encrypted {
+        return Err(Error::Encrypted);
+    }
+    if revision.text().is_none() {
+        return Err(Error::BinaryContent);
+    }
+
+    let (entry, rev) = data.read(&id, Some(rev), user.username())?;
+    let text = entry
+        .revision(rev)
+        .and_then(|revision| revision.text())
+        .ok_or(Error::NotFound)?;
+    Ok(page::Page {
+        html: highlighter.render(&entry, rev, text),
+        cache_control: if cacheable(&entry) {
+            "no-cache"
+        } else {
+            "no-store"
+        },
+    })
 }
 
 #[launch]
 fn rocket() -> _ {
-    let clipboard = Clipboard::init();
-    rocket::build()
+    app(rocket::build())
+}
+
+fn app(rocket: Rocket<Build>) -> Rocket<Build> {
+    let mut config: Config = rocket.figment().extract().expect("invalid config");
+    assert!(
+        config.id_length > 0 && !config.id_alphabet.is_empty(),
+        "id_length and id_alphabet must not be empty"
+    );
+    // generated ids skip `check_id`, so the alphabet itself has to be url
+    // safe, and a repeated character would come up more often than the rest
+    assert!(
+        config
+            .id_alphabet
+            .chars()
+            .all(|c| is_slug(c.encode_utf8(&mut [0; 4]), 1)),
+        "id_alphabet may only contain letters, digits, '-' or '_'"
+    );
+    let mut seen = HashSet::new();
+    config.id_alphabet.retain(|c| seen.insert(c));
+    assert!(config.reap_interval > 0, "reap_interval must not be zero");
+    assert!(
+        config.max_id_length >= config.id_length,
+        "max_id_length must not be shorter than id_length"
+    );
+    let clipboard = match &config.storage {
+        Some(path) => Clipboard::open(path).expect("failed to open storage"),
+        None => Clipboard::init(),
+    };
+    let clipboard = clipboard.with_quota(config.quota());
+    let accounts = match &config.accounts {
+        Some(path) => Accounts::open(path).expect("failed to open accounts"),
+        None => Accounts::init(),
+    };
+    let limiter = RateLimiter::new(config.rate_limits.clone(), config.decrypt_lockout)
+        .with_trusted_proxies(config.trusted_proxies.clone());
+    rocket
         .manage(clipboard)
+        .manage(accounts)
+        .manage(limiter)
+        .manage(config)
+        .manage(Highlighter::new())
         .mount("/", FileServer::from("static"))
-        .mount("/api", routes![get_entry, add_entry, decrypt])
+        .mount("/", routes![raw_entry, download_entry, view_page])
+        .mount(
+            "/api",
+            routes![
+                list_entries,
+                list_tags,
+                tagged_entries,
+                search_entries,
+                get_entry,
+                get_revision,
+                list_revisions,
+                diff_entries,
+                add_entry,
+                add_upload,
+                add_text,
+                update_entry,
+                update_visibility,
+                delete_entry,
+                decrypt,
+                register,
+                login,
+                logout,
+                my_entries
+            ],
+        )
+        .register(
+            "/api",
+            catchers![error::default_catcher, ratelimit::too_many_requests],
+        )
+        .attach(AdHoc::on_liftoff("Expired entry reaper", |rocket| {
+            Box::pin(async move {
+                let clipboard = rocket.state::<Clipboard>().unwrap().clone();
+                let limiter = rocket.state::<RateLimiter>().unwrap().clone();
+                let period = rocket.state::<Config>().unwrap().reap_interval;
+                let mut shutdown = rocket.shutdown();
+                rocket::tokio::spawn(async move {
+                    let mut timer = rocket::tokio::time::interval(Duration::from_secs(period));
+                    loop {
+                        rocket::tokio::select! {
+                            _ = timer.tick() => {
+                                match clipboard.purge_expired(now()) {
+                                    Ok(0) => {}
+                                    Ok(n) => info!("purged {} expired entries", n),
+                                    Err(e) => error!("failed to purge expired entries: {}", e),
+                                }
+                                if let Err(e) = clipboard.flush_views() {
+                                    error!("failed to write view counts: {}", e);
+                                }
+                                limiter.prune();
+                            }
+                            _ = &mut shutdown => break,
+                        }
+                    }
+                });
+            })
+        }))
+        .attach(AdHoc::on_shutdown("View count flush", |rocket| {
+            Box::pin(async move {
+                if let Err(e) = rocket.state::<Clipboard>().unwrap().flush_views() {
+                    error!("failed to write view counts: {}", e);
+                }
+            })
+        }))
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::pad;
+    use super::{app, now, Clipboard, Entry, EntryContent};
+    use rocket::figment::Figment;
+    use rocket::http::{ContentType, Header, Status};
+    use rocket::local::blocking::Client;
+    use serde_json::Value;
+
+    fn client() -> Client {
+        Client::tracked(app(rocket::custom(rocket::Config::debug_default()))).unwrap()
+    }
+
+    fn add(client: &Client, body: &str) -> (Status, Option<Value>) {
+        let res = client
+            .post("/api/add")
+            .header(ContentType::JSON)
+            .body(body)
+            .dispatch();
+        (res.status(), res.into_json())
+    }
+
     #[test]
-    fn one_time_pad() {
-        let pt = String::from("0123456789abcdef");
-        let key = String::from("supersecreptkey!");
-        let ct = pad(&key, &pt).unwrap();
-        assert_eq!(pt, pad(&key, &ct).unwrap());
+    fn generated_and_vanity_ids() {
+        let client = client();
+
+        let (status, body) = add(&client, r#"{"content":"one","encrypted":false}"#);
+        assert_eq!(status, Status::Ok);
+        let id = body.unwrap()["id"].as_str().unwrap().to_string();
+        assert_eq!(id.len(), 12);
+
+        let (status, _) = add(
+            &client,
+            r#"{"id":"mine","content":"two","encrypted":false}"#,
+        );
+        assert_eq!(status, Status::Ok);
+        let (status, _) = add(
+            &client,
+            r#"{"id":"mine","content":"three","encrypted":false}"#,
+        );
+        assert_eq!(status, Status::Conflict);
+
+        let res = client.get("/api/get?id=mine").dispatch();
+        assert_eq!(res.into_json::<Value>().unwrap()["content"], "two");
+    }
+
+    #[test]
+    #[should_panic(expected = "id_alphabet")]
+    fn id_alphabet_must_be_url_safe() {
+        let figment =
+            Figment::from(rocket::Config::debug_default()).merge(("id_alphabet", "abc/?."));
+        app(rocket::custom(figment));
+    }
+
+    #[test]
+    fn updates_create_revisions() {
+        let client = client();
+        let (_, body) = add(&client, r#"{"content":"draft","encrypted":false}"#);
+        let body = body.unwrap();
+        let id = body["id"].as_str().unwrap();
+        let token = body["edit_token"].as_str().unwrap();
+
+        let update = |token: &str| {
+            client
+                .put(format!("/api/entry/{}", id))
+                .header(ContentType::JSON)
+                .header(Header::new("X-Edit-Token", token.to_string()))
+                .body(r#"{"content":"final","encrypted":false}"#)
+                .dispatch()
+                .status()
+        };
+        assert_eq!(update("nope"), Status::Forbidden);
+        assert_eq!(update(token), Status::Ok);
+
+        let res = client.get(format!("/api/get?id={}", id)).dispatch();
+        let body = res.into_json::<Value>().unwrap();
+        assert_eq!(body["content"], "final");
+        assert_eq!(body["revision"], 2);
+
+        let res = client.get(format!("/api/entry/{}?rev=1", id)).dispatch();
+        assert_eq!(res.into_json::<Value>().unwrap()["content"], "draft");
+        let res = client.get(format!("/api/entry/{}?rev=3", id)).dispatch();
+        assert_eq!(res.status(), Status::NotFound);
+
+        let res = client
+            .get(format!("/api/entry/{}/revisions", id))
+            .dispatch();
+        let revisions = res.into_json::<Vec<Value>>().unwrap();
+        assert_eq!(revisions.len(), 2);
+        assert_eq!(revisions[0]["size"], 5);
+    }
+
+    #[test]
+    fn error_responses() {
+        let client = client();
+        let (status, body) = add(&client, r#"{"content":"x","encrypted":true}"#);
+        assert_eq!(status, Status::BadRequest);
+        assert_eq!(body.unwrap()["code"], "missing_key");
+        for expiry in [r#""expires_in":0"#, r#""expires_at":1"#] {
+            let (status, body) = add(
+                &client,
+                &format!(r#"{{"content":"x","encrypted":false,{}}}"#, expiry),
+            );
+            assert_eq!(status, Status::BadRequest);
+            assert_eq!(body.unwrap()["code"], "invalid_expiry");
+        }
+
+        let (_, body) = add(&client, r#"{"content":"x","encrypted":true,"key":"pw"}"#);
+        let id = body.unwrap()["id"].as_str().unwrap().to_string();
+        let decrypt = |key: &str| {
+            client
+                .post(format!("/api/decrypt?id={}", id))
+                .header(ContentType::JSON)
+                .body(format!(r#"{{"key":"{}"}}"#, key))
+                .dispatch()
+        };
+        let res = decrypt("wrong");
+        assert_eq!(res.status(), Status::Forbidden);
+        assert_eq!(res.into_json::<Value>().unwrap()["code"], "wrong_key");
+        assert_eq!(decrypt("pw").into_string().unwrap(), "x");
+
+        let res = client.get("/api/get?id=missing").dispatch();
+        assert_eq!(res.status(), Status::NotFound);
+        assert_eq!(res.into_json::<Value>().unwrap()["code"], "not_found");
+
+        let res = client.put("/api/entry/missing").dispatch();
+        assert_eq!(res.status(), Status::Unauthorized);
+        assert_eq!(res.into_json::<Value>().unwrap()["code"], "unauthorized");
+    }
+
+    #[test]
+    fn expired_entries_are_hidden_and_purged() {
+        let clipboard = Clipboard::init();
+        let content = || EntryContent {
+            content: b"x".to_vec(),
+            encrypted: false,
+            key: None,
+            mime: None,
+            filename: None,
+        };
+        let mut expired = Entry::new(String::from("old"), content().seal().unwrap());
+        expired.expires_at = Some(now() - 1);
+        let mut live = Entry::new(String::from("new"), content().seal().unwrap());
+        live.expires_at = Some(now() + 60);
+        clipboard.add(expired).unwrap();
+        clipboard.add(live).unwrap();
+
+        assert!(clipboard.get("old").is_none());
+        assert!(clipboard.get("new").is_some());
+        assert_eq!(clipboard.purge_expired(now()).unwrap(), 1);
+        assert_eq!(clipboard.purge_expired(now() + 60).unwrap(), 1);
+    }
+
+    #[test]
+    fn read_limits() {
+        let client = client();
+        let (_, body) = add(
+            &client,
+            r#"{"content":"once","encrypted":false,"burn_after_reading":true}"#,
+        );
+        let id = body.unwrap()["id"].as_str().unwrap().to_string();
+        let get = |id: &str| {
+            client
+                .get(format!("/api/get?id={}", id))
+                .dispatch()
+                .status()
+        };
+        assert_eq!(get(&id), Status::Ok);
+        assert_eq!(get(&id), Status::NotFound);
+
+        let (_, body) = add(
+            &client,
+            r#"{"content":"s","encrypted":true,"key":"pw","max_reads":2}"#,
+        );
+        let id = body.unwrap()["id"].as_str().unwrap().to_string();
+        let decrypt = |key: &str| {
+            client
+                .post(format!("/api/decrypt?id={}", id))
+                .header(ContentType::JSON)
+                .body(format!(r#"{{"key":"{}"}}"#, key))
+                .dispatch()
+                .status()
+        };
+        let res = client.get(format!("/api/get?id={}", id)).dispatch();
+        assert_eq!(res.into_json::<Value>().unwrap()["reads_left"], 2);
+        assert_eq!(decrypt("wrong"), Status::Forbidden);
+        assert_eq!(decrypt("pw"), Status::Ok);
+        assert_eq!(decrypt("pw"), Status::Ok);
+        assert_eq!(decrypt("pw"), Status::NotFound);
+
+        let (status, _) = add(
+            &client,
+            r#"{"content":"x","encrypted":false,"max_reads":0}"#,
+        );
+        assert_eq!(status, Status::BadRequest);
+    }
+
+    #[test]
+    fn delete_requires_delete_token() {
+        let client = client();
+        let (_, body) = add(&client, r#"{"content":"x","encrypted":false}"#);
+        let body = body.unwrap();
+        let id = body["id"].as_str().unwrap();
+
+        let delete = |token: &Value| {
+            client
+                .delete(format!("/api/entry/{}", id))
+                .header(Header::new(
+                    "X-Delete-Token",
+                    token.as_str().unwrap().to_string(),
+                ))
+                .dispatch()
+                .status()
+        };
+        assert_eq!(delete(&body["edit_token"]), Status::Forbidden);
+        assert_eq!(delete(&body["delete_token"]), Status::Ok);
+        assert_eq!(delete(&body["delete_token"]), Status::NotFound);
+    }
+
+    #[test]
+    fn diff_revisions_and_entries() {
+        let client = client();
+        let (_, body) = add(&client, r#"{"content":"a\nb\n","encrypted":false}"#);
+        let body = body.unwrap();
+        let id = body["id"].as_str().unwrap().to_string();
+        client
+            .put(format!("/api/entry/{}", id))
+            .header(ContentType::JSON)
+            .header(Header::new(
+                "X-Edit-Token",
+                body["edit_token"].as_str().unwrap().to_string(),
+            ))
+            .body(r#"{"content":"a\nc\n","encrypted":false}"#)
+            .dispatch();
+
+        let res = client.get(format!("/api/diff?a={}", id)).dispatch();
+        let diff = res.into_json::<Value>().unwrap();
+        assert_eq!(
+            diff["unified"],
+            format!("--- {0}@1\n+++ {0}@2\n@@ -1,2 +1,2 @@\n a\n-b\n+c\n", id)
+        );
+        assert_eq!(diff["hunks"][0]["lines"][1]["kind"], "delete");
+
+        let (_, other) = add(&client, r#"{"content":"x","encrypted":true,"key":"pw"}"#);
+        let other = other.unwrap()["id"].as_str().unwrap().to_string();
+        let res = client
+            .get(format!("/api/diff?a={}&b={}", id, other))
+            .dispatch();
+        assert_eq!(res.status(), Status::Forbidden);
+    }
+
+    #[test]
+    fn raw_entries() {
+        let client = client();
+        let (_, body) = add(&client, r#"{"content":"echo hi\n","encrypted":false}"#);
+        let id = body.unwrap()["id"].as_str().unwrap().to_string();
+
+        let res = client.get(format!("/raw/{}", id)).dispatch();
+        assert_eq!(res.status(), Status::Ok);
+        assert_eq!(res.content_type(), Some(ContentType::Plain));
+        let etag = res.headers().get_one("ETag").unwrap().to_string();
+        assert_eq!(res.into_string().unwrap(), "echo hi\n");
+
+        let res = client
+            .get(format!("/raw/{}", id))
+            .header(Header::new("If-None-Match", etag))
+            .dispatch();
+        assert_eq!(res.status(), Status::NotModified);
+
+        let (_, body) = add(&client, r#"{"content":"x","encrypted":true,"key":"pw"}"#);
+        let id = body.unwrap()["id"].as_str().unwrap().to_string();
+        let res = client.get(format!("/raw/{}", id)).dispatch();
+        assert_eq!(res.status(), Status::Forbidden);
+    }
+
+    #[test]
+    fn text_and_multipart_uploads() {
+        let client = client();
+        let res = client
+            .post("/api/add?expires_in=60")
+            .header(ContentType::Plain)
+            .body("plain body")
+            .dispatch();
+        let id = res.into_json::<Value>().unwrap()["id"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        let res = client.get(format!("/raw/{}", id)).dispatch();
+        assert_eq!(res.into_string().unwrap(), "plain body");
+
+        // text/plain without a charset is still plain text, not a custom type
+        let res = client
+            .post("/api/add")
+            .header(ContentType::new("text", "plain"))
+            .body("no charset")
+            .dispatch();
+        let id = res.into_json::<Value>().unwrap()["id"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        let res = client.get(format!("/api/get?id={}", id)).dispatch();
+        assert_eq!(res.into_json::<Value>().unwrap()["mime"], Value::Null);
+        let res = client.get(format!("/raw/{}", id)).dispatch();
+        assert_eq!(res.content_type(), Some(ContentType::Plain));
+
+        let res = client
+            .post("/api/add")
+            .header(ContentType::Plain)
+            .header(Header::new("X-Encryption-Key", "pw"))
+            .body("secret")
+            .dispatch();
+        let id = res.into_json::<Value>().unwrap()["id"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        let res = client.get(format!("/api/get?id={}", id)).dispatch();
+        assert_eq!(res.into_json::<Value>().unwrap()["encrypted"], true);
+
+        let body = "--XX\r\n\
+            Content-Disposition: form-data; name=\"file\"; filename=\"a.txt\"\r\n\
+            Content-Type: text/plain\r\n\r\n\
+            from a file\r\n\
+            --XX\r\n\
+            Content-Disposition: form-data; name=\"id\"\r\n\r\n\
+            upload\r\n\
+            --XX--\r\n";
+        let res = client
+            .post("/api/add")
+            .header(ContentType::new("multipart", "form-data").with_params(("boundary", "XX")))
+            .body(body)
+            .dispatch();
+        assert_eq!(res.status(), Status::Ok);
+        let res = client.get("/raw/upload").dispatch();
+        assert_eq!(res.into_string().unwrap(), "from a file");
+
+        // an HTML form's fields are parsed, other urlencoded bodies are raw
+        let res = client
+            .post("/api/add")
+            .header(ContentType::Form)
+            .body("content=hello+world%21&id=form&title=x")
+            .dispatch();
+        assert_eq!(res.status(), Status::Ok);
+        let res = client.get("/api/get?id=form").dispatch();
+        let body = res.into_json::<Value>().unwrap();
+        assert_eq!(body["content"], "hello world!");
+        assert_eq!(body["title"], "x");
+        let res = client
+            .post("/api/add?id=curl")
+            .header(ContentType::Form)
+            .body("a=1&b=2\n")
+            .dispatch();
+        assert_eq!(res.status(), Status::Ok);
+        let res = client.get("/raw/curl").dispatch();
+        assert_eq!(res.into_string().unwrap(), "a=1&b=2\n");
+    }
+
+    #[test]
+    fn binary_entries() {
+        let client = client();
+        let png = [0x89, b'P', b'N', b'G', 0, 0xff];
+        let res = client
+            .post("/api/add?filename=dot.png")
+            .header(ContentType::PNG)
+            .body(png)
+            .dispatch();
+        let id = res.into_json::<Value>().unwrap()["id"]
+            .as_str()
+            .unwrap()
+            .to_string();
+
+        let res = client.get(format!("/api/get?id={}", id)).dispatch();
+        let body = res.into_json::<Value>().unwrap();
+        assert_eq!(body["encoding"], "base64");
+        assert_eq!(body["content"], "iVBORwD/");
+        assert_eq!(body["mime"], "image/png");
+
+        let res = client.get(format!("/download/{}", id)).dispatch();
+        assert_eq!(res.content_type(), Some(ContentType::PNG));
+        assert_eq!(
+            res.headers().get_one("Content-Disposition"),
+            Some("attachment; filename=\"dot.png\"; filename*=UTF-8''dot.png")
+        );
+        assert_eq!(res.into_bytes().unwrap(), png);
+
+        let (_, body) = add(
+            &client,
+            r#"{"id":"bin","content":"/wAB","encoding":"base64","encrypted":true,"key":"pw"}"#,
+        );
+        assert!(body.is_some());
+        let res = client
+            .post("/api/decrypt?id=bin")
+            .header(ContentType::JSON)
+            .body(r#"{"key":"pw"}"#)
+            .dispatch();
+        assert_eq!(res.content_type(), Some(ContentType::Binary));
+        assert_eq!(res.into_bytes().unwrap(), [0xff, 0, 1]);
+
+        let res = client
+            .get(format!("/api/diff?a={}&b={}", id, id))
+            .dispatch();
+        assert_eq!(res.status(), Status::BadRequest);
+    }
+
+    #[test]
+    fn decrypted_uploads_are_sandboxed() {
+        let client = client();
+        let res = client
+            .post("/api/add?id=page")
+            .header(ContentType::HTML)
+            .header(Header::new("X-Encryption-Key", "pw"))
+            .body("<script>alert(1)</script>")
+            .dispatch();
+        assert_eq!(res.status(), Status::Ok);
+
+        let res = client
+            .post("/api/decrypt?id=page")
+            .header(ContentType::JSON)
+            .body(r#"{"key":"pw"}"#)
+            .dispatch();
+        assert_eq!(res.content_type(), Some(ContentType::HTML));
+        assert_eq!(
+            res.headers().get_one("X-Content-Type-Options"),
+            Some("nosniff")
+        );
+        assert_eq!(
+            res.headers().get_one("Content-Security-Policy"),
+            Some("sandbox")
+        );
+
+        // a cross site form can only send text/plain, not json
+        let res = client
+            .post("/api/decrypt?id=page")
+            .header(ContentType::Plain)
+            .body(r#"{"key":"pw","a":"="}"#)
+            .dispatch();
+        assert_eq!(res.status(), Status::NotFound);
+    }
+
+    #[test]
+    fn limits() {
+        let figment = Figment::from(rocket::Config::debug_default())
+            .merge(("max_content_size", "8 B"))
+            .merge(("max_id_length", 12))
+            .merge(("max_key_length", 4))
+            .merge(("max_entries", 1));
+        let client = Client::tracked(app(rocket::custom(figment))).unwrap();
+
+        let (status, body) = add(&client, r#"{"content":"123456789","encrypted":false}"#);
+        assert_eq!(status, Status::PayloadTooLarge);
+        assert_eq!(body.unwrap()["code"], "content_too_large");
+        let (status, _) = add(&client, r#"{"content":"x","encrypted":true,"key":"12345"}"#);
+        assert_eq!(status, Status::BadRequest);
+        let res = client
+            .post("/api/decrypt?id=missing")
+            .header(ContentType::JSON)
+            .body(r#"{"key":"12345"}"#)
+            .dispatch();
+        assert_eq!(res.into_json::<Value>().unwrap()["code"], "key_too_long");
+        for id in ["thirteen-long", "../x", ""] {
+            let (status, body) = add(
+                &client,
+                &format!(r#"{{"id":"{}","content":"x","encrypted":false}}"#, id),
+            );
+            assert_eq!(status, Status::BadRequest);
+            assert_eq!(body.unwrap()["code"], "invalid_id");
+        }
+
+        let (status, body) = add(
+            &client,
+            r#"{"id":"ok_id-1","content":"x","encrypted":false}"#,
+        );
+        assert_eq!(status, Status::Ok);
+        let token = body.unwrap()["delete_token"].as_str().unwrap().to_string();
+        let (status, body) = add(&client, r#"{"content":"y","encrypted":false}"#);
+        assert_eq!(status, Status::InsufficientStorage);
+        assert_eq!(body.unwrap()["code"], "store_full");
+
+        // deleting frees the slot again
+        let res = client
+            .delete("/api/entry/ok_id-1")
+            .header(Header::new("X-Delete-Token", token))
+            .dispatch();
+        assert_eq!(res.status(), Status::Ok);
+        let (status, _) = add(&client, r#"{"content":"y","encrypted":false}"#);
+        assert_eq!(status, Status::Ok);
+    }
+
+    #[test]
+    fn rate_limits() {
+        let figment = Figment::from(rocket::Config::debug_default())
+            .merge(("rate_limits.add.burst", 1))
+            .merge(("rate_limits.add.per_minute", 1))
+            .merge(("rate_limits.update.burst", 1))
+            .merge(("rate_limits.update.per_minute", 1))
+            .merge(("decrypt_lockout.max_failures", 2))
+            .merge(("decrypt_lockout.seconds", 60))
+            .merge(("trusted_proxies", ["10.0.0.5"]));
+        let client = Client::tracked(app(rocket::custom(figment))).unwrap();
+        let add_from = |ip: &str| {
+            client
+                .post("/api/add")
+                .remote(format!("{}:1234", ip).parse().unwrap())
+                .header(ContentType::Plain)
+                .body("x")
+                .dispatch()
+        };
+        assert_eq!(add_from("10.0.0.1").status(), Status::Ok);
+        let res = add_from("10.0.0.1");
+        assert_eq!(res.status(), Status::TooManyRequests);
+        assert_eq!(res.headers().get_one("Retry-After"), Some("60"));
+        assert_eq!(res.into_json::<Value>().unwrap()["code"], "rate_limited");
+        assert_eq!(add_from("10.0.0.2").status(), Status::Ok);
+        // the address header is only believed from a trusted proxy
+        let add_via = |peer: &str, ip: &str| {
+            client
+                .post("/api/add")
+                .remote(format!("{}:1234", peer).parse().unwrap())
+                .header(Header::new("X-Real-IP", ip.to_string()))
+                .header(ContentType::Plain)
+                .body("x")
+                .dispatch()
+                .status()
+        };
+        assert_eq!(add_via("10.0.0.1", "10.9.9.9"), Status::TooManyRequests);
+        assert_eq!(add_via("10.0.0.5", "10.9.9.8"), Status::Ok);
+        assert_eq!(add_via("10.0.0.5", "10.9.9.7"), Status::Ok);
+        assert_eq!(add_via("10.0.0.5", "10.9.9.7"), Status::TooManyRequests);
+
+        let update = || {
+            client
+                .put("/api/entry/missing")
+                .remote("10.0.0.3:1234".parse().unwrap())
+                .header(Header::new("X-Edit-Token", "guess"))
+                .header(ContentType::JSON)
+                .body(r#"{"content":"x","encrypted":true,"key":"pw"}"#)
+                .dispatch()
+                .status()
+        };
+        assert_eq!(update(), Status::NotFound);
+        assert_eq!(update(), Status::TooManyRequests);
+
+        let (_, body) = add(
+            &client,
+            r#"{"id":"locked","content":"x","encrypted":true,"key":"pw"}"#,
+        );
+        assert!(body.is_some());
+        let decrypt = |key: &str| {
+            client
+                .post("/api/decrypt?id=locked")
+                .header(ContentType::JSON)
+                .body(format!(r#"{{"key":"{}"}}"#, key))
+                .dispatch()
+                .status()
+        };
+        assert_eq!(decrypt("wrong"), Status::Forbidden);
+        assert_eq!(decrypt("wrong"), Status::Forbidden);
+        assert_eq!(decrypt("pw"), Status::TooManyRequests);
+    }
+
+    #[test]
+    fn accounts_own_entries() {
+        let client = client();
+        let post = |uri: &str, body: &str| {
+            client
+                .post(uri.to_string())
+                .header(ContentType::JSON)
+                .body(body)
+                .dispatch()
+        };
+        let credentials = r#"{"username":"alice","password":"correct horse"}"#;
+        assert_eq!(post("/api/register", credentials).status(), Status::Ok);
+        assert_eq!(
+            post("/api/register", credentials).status(),
+            Status::Conflict
+        );
+        let res = post(
+            "/api/login",
+            r#"{"username":"alice","password":"wrong horse"}"#,
+        );
+        assert_eq!(res.status(), Status::Unauthorized);
+        let res = post("/api/login", credentials);
+        let token = res.into_json::<Value>().unwrap()["token"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        let bearer = || Header::new("Authorization", format!("Bearer {}", token));
+
+        let res = client
+            .post("/api/add")
+            .header(ContentType::JSON)
+            .header(bearer())
+            .body(r#"{"id":"mine","content":"x","encrypted":false}"#)
+            .dispatch();
+        assert_eq!(res.status(), Status::Ok);
+        add(
+            &client,
+            r#"{"id":"theirs","content":"y","encrypted":false}"#,
+        );
+
+        let res = client.get("/api/me/entries").header(bearer()).dispatch();
+        let entries = res.into_json::<Vec<Value>>().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0]["id"], "mine");
+        assert_eq!(entries[0]["size"], 1);
+
+        let delete = |id: &str| {
+            client
+                .delete(format!("/api/entry/{}", id))
+                .header(bearer())
+                .dispatch()
+                .status()
+        };
+        assert_eq!(delete("theirs"), Status::Forbidden);
+        assert_eq!(delete("mine"), Status::Ok);
+
+        let res = client.post("/api/logout").header(bearer()).dispatch();
+        assert_eq!(res.status(), Status::Ok);
+        let res = client.get("/api/me/entries").header(bearer()).dispatch();
+        assert_eq!(res.status(), Status::Unauthorized);
+        let res = client
+            .post("/api/add")
+            .header(ContentType::JSON)
+            .header(bearer())
+            .body(r#"{"content":"x","encrypted":false}"#)
+            .dispatch();
+        assert_eq!(res.status(), Status::Unauthorized);
+    }
+
+    fn login(client: &Client, username: &str) -> Header<'static> {
+        let credentials = format!(
+            r#"{{"username":"{}","password":"correct horse"}}"#,
+            username
+        );
+        let post = |uri| {
+            client
+                .post(uri)
+                .header(ContentType::JSON)
+                .body(&credentials)
+                .dispatch()
+        };
+        post("/api/register");
+        let token = post("/api/login").into_json::<Value>().unwrap()["token"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        Header::new("Authorization", format!("Bearer {}", token))
+    }
+
+    #[test]
+    fn private_entries() {
+        let client = client();
+        let alice = login(&client, "alice");
+        let bob = login(&client, "bob");
+        let carol = login(&client, "carol");
+
+        let (status, body) = add(
+            &client,
+            r#"{"content":"x","encrypted":false,"visibility":"private"}"#,
+        );
+        assert_eq!(status, Status::BadRequest);
+        assert_eq!(body.unwrap()["code"], "missing_owner");
+
+        let res = client
+            .post("/api/add")
+            .header(ContentType::JSON)
+            .header(alice.clone())
+            .body(r#"{"id":"secret","content":"x","encrypted":false,"visibility":"private","shared_with":["bob"]}"#)
+            .dispatch();
+        assert_eq!(res.status(), Status::Ok);
+
+        let get = |user: Option<&Header<'static>>| {
+            let mut req = client.get("/api/get?id=secret");
+            if let Some(user) = user {
+                req = req.header(user.clone());
+            }
+            req.dispatch().status()
+        };
+        assert_eq!(get(None), Status::NotFound);
+        assert_eq!(get(Some(&carol)), Status::NotFound);
+        assert_eq!(get(Some(&alice)), Status::Ok);
+        assert_eq!(get(Some(&bob)), Status::Ok);
+        let res = client.get("/raw/secret").dispatch();
+        assert_eq!(res.status(), Status::NotFound);
+
+        let res = client
+            .put("/api/entry/secret/visibility")
+            .header(ContentType::JSON)
+            .header(bob.clone())
+            .body(r#"{"visibility":"unlisted"}"#)
+            .dispatch();
+        assert_eq!(res.status(), Status::Forbidden);
+        let res = client
+            .put("/api/entry/secret/visibility")
+            .header(ContentType::JSON)
+            .header(alice.clone())
+            .body(r#"{"visibility":"unlisted"}"#)
+            .dispatch();
+        assert_eq!(res.status(), Status::Ok);
+        assert_eq!(get(None), Status::Ok);
+    }
+
+    #[test]
+    fn public_listing() {
+        let client = client();
+        for id in ["p1", "p2", "p3"] {
+            add(
+                &client,
+                &format!(
+                    r#"{{"id":"{}","title":"paste {}","content":"x","encrypted":false,"visibility":"public"}}"#,
+                    id, id
+                ),
+            );
+        }
+        add(
+            &client,
+            r#"{"id":"hidden","content":"x","encrypted":false}"#,
+        );
+
+        let page = |query: &str| {
+            client
+                .get(format!("/api/entries?{}", query))
+                .dispatch()
+                .into_json::<Value>()
+                .unwrap()
+        };
+        let first = page("limit=2");
+        let ids: Vec<_> = first["entries"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|entry| entry["id"].as_str().unwrap())
+            .collect();
+        assert_eq!(ids, ["p3", "p2"]);
+        assert_eq!(first["entries"][0]["title"], "paste p3");
+        assert!(first["entries"][0].get("content").is_none());
+
+        let cursor = first["next_cursor"].as_str().unwrap();
+        let second = page(&format!("limit=2&cursor={}", cursor));
+        assert_eq!(second["entries"].as_array().unwrap().len(), 1);
+        assert_eq!(second["entries"][0]["id"], "p1");
+        assert!(second["next_cursor"].is_null());
+
+        let res = client.get("/api/entries?cursor=nope").dispatch();
+        assert_eq!(res.status(), Status::BadRequest);
+    }
+
+    #[test]
+    fn search() {
+        let client = client();
+        let (_, body) = add(
+            &client,
+            r#"{"id":"snippet","title":"Retry helper","content":"fn retry_with_backoff() {}\n","encrypted":false,"visibility":"public"}"#,
+        );
+        let token = body.unwrap()["delete_token"].as_str().unwrap().to_string();
+        add(
+            &client,
+            r#"{"content":"fn retry_with_backoff() {}","encrypted":false}"#,
+        );
+        add(
+            &client,
+            r#"{"content":"retry","encrypted":true,"key":"pw","visibility":"public"}"#,
+        );
+
+        let search = |q: &str| {
+            client
+                .get(format!("/api/search?q={}", q))
+                .dispatch()
+                .into_json::<Vec<Value>>()
+                .unwrap()
+        };
+        let results = search("retry_with_backoff");
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0]["id"], "snippet");
+        assert_eq!(results[0]["snippet"], "fn retry_with_backoff() {}");
+        assert_eq!(search("%22retry%20helper%22").len(), 1);
+        assert!(search("%22helper%20retry%22").is_empty());
+
+        client
+            .delete("/api/entry/snippet")
+            .header(Header::new("X-Delete-Token", token))
+            .dispatch();
+        assert!(search("retry").is_empty());
+
+        let res = client.get("/api/search?q=%22%22").dispatch();
+        assert_eq!(res.status(), Status::BadRequest);
+    }
+
+    #[test]
+    fn metadata() {
+        let client = client();
+        let (_, body) = add(
+            &client,
+            r#"{"id":"meta","title":" Build script ","language":"rust","tags":["CI","build","ci"],"content":"fn main() {}","encrypted":false}"#,
+        );
+        assert!(body.is_some());
+
+        let get = || {
+            client
+                .get("/api/get?id=meta")
+                .dispatch()
+                .into_json::<Value>()
+                .unwrap()
+        };
+        let body = get();
+        assert_eq!(body["title"], "Build script");
+        assert_eq!(body["language"], "rust");
+        assert_eq!(body["tags"], serde_json::json!(["ci", "build"]));
+        assert_eq!(body["size"], 12);
+        assert_eq!(body["views"], 1);
+        assert_eq!(body["reads_left"], Value::Null);
+        assert!(body["created_at"].as_u64().unwrap() > 0);
+        assert_eq!(get()["views"], 2);
+
+        let res = client
+            .post("/api/add?title=notes&language=text&tags=a&tags=b")
+            .header(ContentType::Plain)
+            .body("x")
+            .dispatch();
+        let id = res.into_json::<Value>().unwrap()["id"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        let res = client.get(format!("/api/get?id={}", id)).dispatch();
+        assert_eq!(
+            res.into_json::<Value>().unwrap()["tags"],
+            serde_json::json!(["a", "b"])
+        );
+
+        let (status, body) = add(
+            &client,
+            r#"{"tags":["no spaces"],"content":"x","encrypted":false}"#,
+        );
+        assert_eq!(status, Status::BadRequest);
+        assert_eq!(body.unwrap()["code"], "invalid_tag");
+    }
+
+    #[test]
+    fn tags() {
+        let client = client();
+        let mut tokens = std::collections::HashMap::new();
+        for (id, tags, visibility) in [
+            ("deploy", r#"["runbook","ops"]"#, "public"),
+            ("restart", r#"["runbook"]"#, "public"),
+            ("secret", r#"["runbook"]"#, "unlisted"),
+            ("snippet", r#"["rust"]"#, "public"),
+        ] {
+            let body = format!(
+                r#"{{"id":"{}","tags":{},"visibility":"{}","content":"x","encrypted":false}}"#,
+                id, tags, visibility
+            );
+            let (_, body) = add(&client, &body);
+            let token = body.unwrap()["delete_token"].as_str().unwrap().to_string();
+            tokens.insert(id, token);
+        }
+
+        let res = client.get("/api/tags").dispatch();
+        assert_eq!(
+            res.into_json::<Value>().unwrap(),
+            serde_json::json!([
+                {"tag": "runbook", "count": 2},
+                {"tag": "ops", "count": 1},
+                {"tag": "rust", "count": 1},
+            ])
+        );
+        let res = client.get("/api/tags?limit=1").dispatch();
+        assert_eq!(
+            res.into_json::<Value>().unwrap().as_array().unwrap().len(),
+            1
+        );
+
+        let ids = |body: &Value| -> Vec<String> {
+            body["entries"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|entry| entry["id"].as_str().unwrap().to_string())
+                .collect()
+        };
+        let body = client
+            .get("/api/tags/Runbook?limit=1")
+            .dispatch()
+            .into_json::<Value>()
+            .unwrap();
+        let mut seen = ids(&body);
+        let cursor = body["next_cursor"].as_str().unwrap();
+        let body = client
+            .get(format!("/api/tags/runbook?limit=1&cursor={}", cursor))
+            .dispatch()
+            .into_json::<Value>()
+            .unwrap();
+        seen.extend(ids(&body));
+        assert!(body["next_cursor"].is_null());
+        seen.sort();
+        assert_eq!(seen, ["deploy", "restart"]);
+
+        client
+            .delete("/api/entry/deploy")
+            .header(Header::new("X-Delete-Token", tokens["deploy"].clone()))
+            .dispatch();
+        let res = client.get("/api/tags").dispatch();
+        assert_eq!(
+            res.into_json::<Value>().unwrap(),
+            serde_json::json!([
+                {"tag": "runbook", "count": 1},
+                {"tag": "rust", "count": 1},
+            ])
+        );
+        let res = client.get("/api/tags/ops").dispatch();
+        assert!(ids(&res.into_json::<Value>().unwrap()).is_empty());
+        let res = client.get("/api/tags/not%20a%20tag").dispatch();
+        assert_eq!(res.status(), Status::BadRequest);
+    }
+
+    #[test]
+    fn highlighted_pages() {
+        let client = client();
+        let (_, body) = add(
+            &client,
+            r#"{"id":"hl","title":"<b>main</b>","language":"rust","content":"fn main() {\n    let s = \"</div>\";\n}\n","encrypted":false}"#,
+        );
+        assert!(body.is_some());
+
+        let res = client.get("/p/hl").dispatch();
+        assert_eq!(res.status(), Status::Ok);
+        assert_eq!(res.content_type(), Some(ContentType::HTML));
+        assert_eq!(res.headers().get_one("Cache-Control"), Some("no-cache"));
+        let html = res.into_string().unwrap();
+        assert!(html.contains("<title>&lt;b&gt;main&lt;/b&gt;</title>"));
+        assert!(html.contains("Rust"));
+        assert!(html.contains("id=\"L3\""));
+        assert!(!html.contains("id=\"L4\""));
+        assert!(html.contains("&lt;/div&gt;"));
+        assert!(html.contains("<span style="));
+
+        let (_, body) = add(
+            &client,
+            r##"{"content":"#!/usr/bin/env python3\nprint(1)","encrypted":false,"burn_after_reading":true}"##,
+        );
+        let id = body.unwrap()["id"].as_str().unwrap().to_string();
+        let res = client.get(format!("/p/{}", id)).dispatch();
+        assert_eq!(res.headers().get_one("Cache-Control"), Some("no-store"));
+        assert!(res.into_string().unwrap().contains("Python"));
+        let res = client.get(format!("/p/{}", id)).dispatch();
+        assert_eq!(res.status(), Status::NotFound);
+
+        let (_, body) = add(&client, r#"{"content":"x","encrypted":true,"key":"pw"}"#);
+        let id = body.unwrap()["id"].as_str().unwrap().to_string();
+        let res = client.get(format!("/p/{}", id)).dispatch();
+        assert_eq!(res.status(), Status::Forbidden);
+    }
+
+    #[test]
+    fn web_ui() {
+        let client = client();
+        for (path, content_type) in [
+            ("/", ContentType::HTML),
+            ("/view.html", ContentType::HTML),
+            ("/create.js", ContentType::JavaScript),
+            ("/view.js", ContentType::JavaScript),
+            ("/style.css", ContentType::CSS),
+        ] {
+            let res = client.get(path).dispatch();
+            assert_eq!(res.status(), Status::Ok, "{}", path);
+            assert_eq!(res.content_type(), Some(content_type), "{}", path);
+        }
+
+        // what the create form sends when options are left empty
+        let (status, body) = add(
+            &client,
+            r#"{"content":"x","language":null,"expires_in":null,"burn_after_reading":false,"encrypted":false,"key":null}"#,
+        );
+        assert_eq!(status, Status::Ok);
+        assert!(body.unwrap()["delete_token"].is_string());
     }
 }
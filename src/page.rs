@@ -0,0 +1,194 @@
+use crate::entry::Entry;
+use rocket::http::ContentType;
+use rocket::request::Request;
+use rocket::response::{self, Responder, Response};
+use std::fmt::Write;
+use std::io::Cursor;
+use std::path::Path;
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{Theme, ThemeSet};
+use syntect::html::{styled_line_to_highlighted_html, IncludeBackground};
+use syntect::parsing::{SyntaxReference, SyntaxSet};
+use syntect::util::LinesWithEndings;
+
+const THEME: &str = "InspiredGitHub";
+// Highlighting runs on the request thread and gets slow on large inputs,
+// so anything bigger is shown as plain text.
+const MAX_HIGHLIGHT_SIZE: usize = 256 * 1024;
+
+const STYLE: &str = "\
+body { margin: 0; font-family: sans-serif; color: #24292e; background: #fff; }
+header { padding: 0.75em 1em; border-bottom: 1px solid #e1e4e8; }
+header h1 { margin: 0; font-size: 1.2em; }
+header p { margin: 0.25em 0 0; color: #586069; font-size: 0.9em; }
+header a { margin-right: 0.75em; }
+.code { font: 13px/1.5 monospace; padding: 0.5em 0; overflow-x: auto; }
+.line { white-space: pre; min-height: 1.5em; padding-right: 1em; }
+.line:target { background: #fffbdd; }
+.num { display: inline-block; width: 4em; margin-right: 1em; padding-right: 0.5em;
+       text-align: right; color: #959da5; text-decoration: none; user-select: none; }
+.num::before { content: attr(data-line); }
+";
+
+// Syntax definitions and themes are loaded once at startup, parsing them is
+// far too slow to do per request.
+pub struct Highlighter {
+    syntaxes: SyntaxSet,
+    theme: Theme,
+}
+
+impl Highlighter {
+    pub fn new() -> Highlighter {
+        let mut themes = ThemeSet::load_defaults();
+        Highlighter {
+            syntaxes: SyntaxSet::load_defaults_newlines(),
+            theme: themes.themes.remove(THEME).expect("missing default theme"),
+        }
+    }
+
+    // The entry's language wins, then the file extension, then whatever the
+    // first line gives away (shebangs, doctypes, modelines).
+    fn syntax(
+        &self,
+        text: &str,
+        language: Option<&str>,
+        filename: Option<&str>,
+    ) -> &SyntaxReference {
+        if text.len() > MAX_HIGHLIGHT_SIZE {
+            return self.syntaxes.find_syntax_plain_text();
+        }
+        let extension = filename
+            .map(Path::new)
+            .and_then(Path::extension)
+            .and_then(|extension| extension.to_str());
+        language
+            .and_then(|language| self.syntaxes.find_syntax_by_token(language))
+            .or_else(|| extension.and_then(|ext| self.syntaxes.find_syntax_by_extension(ext)))
+            .or_else(|| self.syntaxes.find_syntax_by_first_line(text))
+            .unwrap_or_else(|| self.syntaxes.find_syntax_plain_text())
+    }
+
+    // One block per line, each with an anchor so `#L12` links to line 12.
+    // The line numbers are drawn by CSS so they aren't copied with the code.
+    fn lines(&self, text: &str, syntax: &SyntaxReference) -> String {
+        let mut highlighter = HighlightLines::new(syntax, &self.theme);
+        let mut html = String::new();
+        for (line, n) in LinesWithEndings::from(text).zip(1..) {
+            let code = highlighter
+                .highlight_line(line, &self.syntaxes)
+                .ok()
+                .and_then(|mut regions| {
+                    for region in &mut regions {
+                        region.1 = region.1.trim_end_matches(['\n', '\r']);
+                    }
+                    styled_line_to_highlighted_html(&regions, IncludeBackground::No).ok()
+                })
+                .unwrap_or_else(|| escape(line.trim_end_matches(['\n', '\r'])));
+            let _ = writeln!(
+                html,
+                "<div class=\"line\" id=\"L{0}\"><a class=\"num\" href=\"#L{0}\" data-line=\"{0}\"></a>{1}</div>",
+                n, code
+            );
+        }
+        html
+    }
+
+    pub fn render(&self, entry: &Entry, rev: usize, text: &str) -> String {
+        let revision = entry.revision(rev).unwrap_or(&entry.current);
+        let syntax = self.syntax(
+            text,
+            entry.language.as_deref(),
+            revision.filename.as_deref(),
+        );
+        let title = escape(entry.title.as_deref().unwrap_or(&entry.id));
+        let id = escape(&entry.id);
+        format!(
+            "<!DOCTYPE html>
+<html>
+<head>
+<meta charset=\"utf-8\">
+<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">
+<title>{title}</title>
+<style>{style}</style>
+</head>
+<body>
+<header>
+<h1>{title}</h1>
+<p>{language} &middot; revision {rev} &middot; \
+<a href=\"/raw/{id}?rev={rev}\">raw</a><a href=\"/download/{id}?rev={rev}\">download</a></p>
+</header>
+<div class=\"code\">
+{lines}</div>
+</body>
+</html>
+",
+            title = title,
+            style = STYLE,
+            language = escape(&syntax.name),
+            rev = rev,
+            id = id,
+            lines = self.lines(text, syntax),
+        )
+    }
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+pub struct Page {
+    pub html: String,
+    pub cache_control: &'static str,
+}
+
+impl<'r> Responder<'r, 'static> for Page {
+    fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+        Response::build()
+            .header(ContentType::HTML)
+            .raw_header("Cache-Control", self.cache_control)
+            // everything on the page is rendered here, it never needs scripts
+            .raw_header(
+                "Content-Security-Policy",
+                "default-src 'none'; style-src 'unsafe-inline'",
+            )
+            .raw_header("X-Content-Type-Options", "nosniff")
+            .sized_body(self.html.len(), Cursor::new(self.html))
+            .ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Highlighter;
+
+    #[test]
+    fn syntax_detection() {
+        let highlighter = Highlighter::new();
+        let name =
+            |text, language, filename| highlighter.syntax(text, language, filename).name.clone();
+        assert_eq!(name("fn main() {}", Some("rust"), None), "Rust");
+        assert_eq!(name("x = 1", Some("py"), Some("a.rs")), "Python");
+        assert_eq!(name("x = 1", Some("nonsense"), Some("a.rs")), "Rust");
+        assert_eq!(
+            name("#!/bin/bash\necho hi", None, None),
+            "Bourne Again Shell (bash)"
+        );
+        assert_eq!(name("just words", None, None), "Plain Text");
+
+        let lines = highlighter.lines("a <b>\n\nc", highlighter.syntax("", None, None));
+        assert_eq!(lines.matches("class=\"line\"").count(), 3);
+        assert!(lines.contains("id=\"L3\""));
+        assert!(lines.contains("&lt;b&gt;"));
+    }
+}
@@ -0,0 +1,308 @@
+use crate::Error;
+use rocket::http::Status;
+use rocket::request::{self, FromRequest, Request};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// Token bucket: up to `burst` requests at once, refilled at `per_minute`.
+#[derive(Debug, Deserialize, Clone, Copy)]
+pub struct Limit {
+    pub burst: u32,
+    pub per_minute: u32,
+}
+
+impl Limit {
+    fn rate(&self) -> f64 {
+        f64::from(self.per_minute) / 60.0
+    }
+}
+
+pub fn default_limits() -> HashMap<String, Limit> {
+    HashMap::from([
+        (
+            String::from("add"),
+            Limit {
+                burst: 10,
+                per_minute: 30,
+            },
+        ),
+        (
+            String::from("update"),
+            Limit {
+                burst: 10,
+                per_minute: 30,
+            },
+        ),
+        (
+            String::from("decrypt"),
+            Limit {
+                burst: 5,
+                per_minute: 10,
+            },
+        ),
+        (
+            String::from("login"),
+            Limit {
+                burst: 5,
+                per_minute: 10,
+            },
+        ),
+    ])
+}
+
+// After `max_failures` wrong keys for one entry, decrypting it is refused for
+// `seconds`, no matter which client asks.
+#[derive(Debug, Deserialize, Clone, Copy)]
+pub struct Lockout {
+    pub max_failures: u32,
+    pub seconds: u64,
+}
+
+impl Default for Lockout {
+    fn default() -> Lockout {
+        Lockout {
+            max_failures: 5,
+            seconds: 300,
+        }
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+struct Failures {
+    count: u32,
+    since: Instant,
+    locked_until: Option<Instant>,
+}
+
+#[derive(Clone)]
+pub struct RateLimiter {
+    limits: HashMap<String, Limit>,
+    lockout: Lockout,
+    buckets: Arc<Mutex<HashMap<(&'static str, IpAddr), Bucket>>>,
+    failures: Arc<Mutex<HashMap<String, Failures>>>,
+    trusted_proxies: Vec<IpAddr>,
+}
+
+impl RateLimiter {
+    pub fn new(limits: HashMap<String, Limit>, lockout: Lockout) -> RateLimiter {
+        RateLimiter {
+            limits,
+            lockout,
+            buckets: Arc::new(Mutex::new(HashMap::new())),
+            failures: Arc::new(Mutex::new(HashMap::new())),
+            trusted_proxies: Vec::new(),
+        }
+    }
+
+    pub fn with_trusted_proxies(self, trusted_proxies: Vec<IpAddr>) -> RateLimiter {
+        RateLimiter {
+            trusted_proxies,
+            ..self
+        }
+    }
+
+    // Takes a token for `route`, or returns how many seconds until the next
+    // one is available.
+    pub fn check(&self, route: &'static str, ip: IpAddr) -> Result<(), u64> {
+        let limit = match self.limits.get(route) {
+            Some(limit) => *limit,
+            None => return Ok(()),
+        };
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets.entry((route, ip)).or_insert(Bucket {
+            tokens: f64::from(limit.burst),
+            updated: now,
+        });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * limit.rate()).min(f64::from(limit.burst));
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else if limit.per_minute == 0 {
+            Err(60)
+        } else {
+            Err(((1.0 - bucket.tokens) / limit.rate()).ceil() as u64)
+        }
+    }
+
+    pub fn check_lockout(&self, id: &str) -> Result<(), Error> {
+        let failures = self.failures.lock().unwrap();
+        match failures.get(id).and_then(|f| f.locked_until) {
+            Some(until) => match until.checked_duration_since(Instant::now()) {
+                Some(left) => Err(Error::RateLimited(retry_secs(left))),
+                None => Ok(()),
+            },
+            None => Ok(()),
+        }
+    }
+
+    // Failures are counted within a window as long as the lockout itself,
+    // so a slow trickle of guesses is still caught.
+    pub fn record_failure(&self, id: &str) {
+        let now = Instant::now();
+        let window = Duration::from_secs(self.lockout.seconds);
+        let mut failures = self.failures.lock().unwrap();
+        let entry = failures.entry(id.to_string()).or_insert(Failures {
+            count: 0,
+            since: now,
+            locked_until: None,
+        });
+        if now.duration_since(entry.since) > window {
+            *entry = Failures {
+                count: 0,
+                since: now,
+                locked_until: None,
+            };
+        }
+        entry.count += 1;
+        if entry.count >= self.lockout.max_failures {
+            entry.locked_until = Some(now + window);
+            entry.count = 0;
+            entry.since = now;
+        }
+    }
+
+    pub fn clear_failures(&self, id: &str) {
+        self.failures.lock().unwrap().remove(id);
+    }
+
+    // Drops state that no longer limits anyone: refilled buckets and
+    // failures outside their window.
+    pub fn prune(&self) {
+        let now = Instant::now();
+        self.buckets.lock().unwrap().retain(|(route, _), bucket| {
+            let limit = self.limits[*route];
+            let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+            bucket.tokens + elapsed * limit.rate() < f64::from(limit.burst)
+        });
+        let window = Duration::from_secs(self.lockout.seconds);
+        self.failures.lock().unwrap().retain(|_, failures| {
+            let locked = failures.locked_until.is_some_and(|until| until > now);
+            locked || now.duration_since(failures.since) <= window
+        });
+    }
+}
+
+fn retry_secs(left: Duration) -> u64 {
+    left.as_secs() + u64::from(left.subsec_nanos() > 0)
+}
+
+// Set by a failing guard for the 429 catcher, which has no other way of
+// learning the retry delay.
+struct RetryAfter(u64);
+
+fn rate_limit(req: &Request<'_>, route: &'static str) -> request::Outcome<(), ()> {
+    let limiter = match req.rocket().state::<RateLimiter>() {
+        Some(limiter) => limiter,
+        None => return request::Outcome::Success(()),
+    };
+    // `client_ip` takes any client's word for it through Rocket's `ip_header`,
+    // so it is only used for requests coming through a trusted proxy. Local
+    // clients without an address aren't limited.
+    let ip = match req.remote().map(|addr| addr.ip()) {
+        Some(peer) if limiter.trusted_proxies.contains(&peer) => req.client_ip().unwrap_or(peer),
+        Some(peer) => peer,
+        None => return request::Outcome::Success(()),
+    };
+    match limiter.check(route, ip) {
+        Ok(()) => request::Outcome::Success(()),
+        Err(secs) => {
+            req.local_cache(|| RetryAfter(secs));
+            request::Outcome::Error((Status::TooManyRequests, ()))
+        }
+    }
+}
+
+pub struct AddLimit;
+pub struct UpdateLimit;
+pub struct DecryptLimit;
+pub struct LoginLimit;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AddLimit {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        rate_limit(req, "add").map(|_| AddLimit)
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for UpdateLimit {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        rate_limit(req, "update").map(|_| UpdateLimit)
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for DecryptLimit {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        rate_limit(req, "decrypt").map(|_| DecryptLimit)
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for LoginLimit {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        rate_limit(req, "login").map(|_| LoginLimit)
+    }
+}
+
+#[catch(429)]
+pub fn too_many_requests(req: &Request) -> Error {
+    let RetryAfter(secs) = req.local_cache(|| RetryAfter(1));
+    Error::RateLimited(*secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Limit, Lockout, RateLimiter};
+    use std::collections::HashMap;
+
+    #[test]
+    fn buckets_and_lockout() {
+        let limits = HashMap::from([(
+            String::from("add"),
+            Limit {
+                burst: 2,
+                per_minute: 1,
+            },
+        )]);
+        let limiter = RateLimiter::new(
+            limits,
+            Lockout {
+                max_failures: 2,
+                seconds: 60,
+            },
+        );
+        let ip = "127.0.0.1".parse().unwrap();
+        let other = "10.0.0.1".parse().unwrap();
+        assert!(limiter.check("add", ip).is_ok());
+        assert!(limiter.check("add", ip).is_ok());
+        assert_eq!(limiter.check("add", ip), Err(60));
+        assert!(limiter.check("add", other).is_ok());
+        assert!(limiter.check("decrypt", ip).is_ok());
+
+        limiter.record_failure("a");
+        assert!(limiter.check_lockout("a").is_ok());
+        limiter.record_failure("a");
+        assert!(limiter.check_lockout("a").is_err());
+        assert!(limiter.check_lockout("b").is_ok());
+    }
+}
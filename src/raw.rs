@@ -0,0 +1,92 @@
+use rocket::http::{ContentType, Status};
+use rocket::request::{self, FromRequest, Request};
+use rocket::response::{self, Responder, Response};
+use sha2::{Digest, Sha256};
+use std::io::Cursor;
+
+pub struct IfNoneMatch(pub Option<String>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for IfNoneMatch {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let etag = req.headers().get_one("If-None-Match").map(str::to_string);
+        request::Outcome::Success(IfNoneMatch(etag))
+    }
+}
+
+// Only ever built once per response, the size difference doesn't matter.
+#[allow(clippy::large_enum_variant)]
+pub enum Raw {
+    Content {
+        body: Vec<u8>,
+        content_type: ContentType,
+        disposition: Option<String>,
+        etag: String,
+        cache_control: &'static str,
+    },
+    NotModified {
+        etag: String,
+    },
+}
+
+impl<'r> Responder<'r, 'static> for Raw {
+    fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+        match self {
+            Raw::Content {
+                body,
+                content_type,
+                disposition,
+                etag,
+                cache_control,
+            } => {
+                let mut response = Response::build();
+                if let Some(disposition) = disposition {
+                    response.raw_header("Content-Disposition", disposition);
+                }
+                response
+                    .header(content_type)
+                    .raw_header("ETag", etag)
+                    .raw_header("Cache-Control", cache_control)
+                    // uploads carry their own content type, which must not
+                    // run scripts on our origin if it happens to be html
+                    .raw_header("X-Content-Type-Options", "nosniff")
+                    .raw_header("Content-Security-Policy", "sandbox")
+                    .sized_body(body.len(), Cursor::new(body))
+                    .ok()
+            }
+            Raw::NotModified { etag } => Response::build()
+                .status(Status::NotModified)
+                .raw_header("ETag", etag)
+                .raw_header("Cache-Control", "no-cache")
+                .ok(),
+        }
+    }
+}
+
+pub fn etag(content: &[u8]) -> String {
+    let hash = Sha256::digest(content);
+    let hex: String = hash[..16].iter().map(|b| format!("{:02x}", b)).collect();
+    format!("\"{}\"", hex)
+}
+
+// The plain `filename` is an ascii fallback for old clients, `filename*`
+// carries the real name (RFC 6266).
+pub fn attachment(filename: &str) -> String {
+    let fallback: String = filename
+        .chars()
+        .map(|c| if c.is_ascii() { c } else { '_' })
+        .collect();
+    let encoded: String = filename
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'.' | b'-' | b'_' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect();
+    format!(
+        "attachment; filename=\"{}\"; filename*=UTF-8''{}",
+        fallback, encoded
+    )
+}
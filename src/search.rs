@@ -0,0 +1,229 @@
+use std::collections::{HashMap, HashSet};
+
+// BM25 parameters, the usual defaults.
+const K1: f64 = 1.2;
+const B: f64 = 0.75;
+// A term in the title counts as much as this many in the content.
+const TITLE_WEIGHT: f64 = 3.0;
+const SNIPPET_LENGTH: usize = 200;
+
+// Lowercased runs of letters, digits and underscores, so identifiers in
+// code stay whole.
+pub fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .filter(|token| !token.is_empty())
+        .map(str::to_lowercase)
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Query {
+    pub terms: Vec<String>,
+    pub phrases: Vec<Vec<String>>,
+}
+
+impl Query {
+    // Words in double quotes form a phrase, an unclosed quote runs to the end.
+    pub fn parse(query: &str) -> Query {
+        let mut parsed = Query::default();
+        for (i, part) in query.split('"').enumerate() {
+            let tokens: Vec<String> = tokenize(part).collect();
+            if i % 2 == 1 && tokens.len() > 1 {
+                parsed.phrases.push(tokens);
+            } else {
+                parsed.terms.extend(tokens);
+            }
+        }
+        parsed
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty() && self.phrases.is_empty()
+    }
+
+    fn all_terms(&self) -> impl Iterator<Item = &String> {
+        self.terms.iter().chain(self.phrases.iter().flatten())
+    }
+
+    // First line of `text` containing one of the query's terms.
+    pub fn snippet(&self, text: &str) -> Option<String> {
+        let terms: HashSet<&String> = self.all_terms().collect();
+        let line = text
+            .lines()
+            .find(|line| tokenize(line).any(|token| terms.contains(&token)))?;
+        Some(line.trim().chars().take(SNIPPET_LENGTH).collect())
+    }
+}
+
+struct Document {
+    terms: Vec<String>,
+    title_len: u32,
+    len: u32,
+}
+
+// Positional inverted index. Title and content share one position space with
+// a gap between them, so phrases never run from the title into the content.
+#[derive(Default)]
+pub struct SearchIndex {
+    postings: HashMap<String, HashMap<String, Vec<u32>>>,
+    documents: HashMap<String, Document>,
+    total_len: u64,
+}
+
+impl SearchIndex {
+    pub fn insert(&mut self, id: &str, title: Option<&str>, content: &str) {
+        self.remove(id);
+        let title: Vec<String> = title.map(|t| tokenize(t).collect()).unwrap_or_default();
+        let title_len = title.len() as u32;
+        let tokens = title
+            .into_iter()
+            .zip(0..)
+            .chain(tokenize(content).zip(title_len + 1..));
+
+        let mut terms = HashSet::new();
+        let mut len = 0;
+        for (token, position) in tokens {
+            self.postings
+                .entry(token.clone())
+                .or_default()
+                .entry(id.to_string())
+                .or_default()
+                .push(position);
+            terms.insert(token);
+            len += 1;
+        }
+        self.total_len += u64::from(len);
+        self.documents.insert(
+            id.to_string(),
+            Document {
+                terms: terms.into_iter().collect(),
+                title_len,
+                len,
+            },
+        );
+    }
+
+    pub fn remove(&mut self, id: &str) {
+        let document = match self.documents.remove(id) {
+            Some(document) => document,
+            None => return,
+        };
+        self.total_len -= u64::from(document.len);
+        for term in document.terms {
+            if let Some(postings) = self.postings.get_mut(&term) {
+                postings.remove(id);
+                if postings.is_empty() {
+                    self.postings.remove(&term);
+                }
+            }
+        }
+    }
+
+    // Ids of documents matching every term and phrase, best first.
+    pub fn search(&self, query: &Query) -> Vec<(String, f64)> {
+        let mut candidates: Option<HashSet<&String>> = None;
+        for term in query.all_terms() {
+            let ids: HashSet<&String> = match self.postings.get(term) {
+                Some(postings) => postings.keys().collect(),
+                None => return Vec::new(),
+            };
+            candidates = Some(match candidates {
+                Some(candidates) => &candidates & &ids,
+                None => ids,
+            });
+        }
+
+        let mut results: Vec<(String, f64)> = candidates
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|id| {
+                query
+                    .phrases
+                    .iter()
+                    .all(|phrase| self.contains_phrase(id, phrase))
+            })
+            .map(|id| (id.clone(), self.score(id, query)))
+            .collect();
+        results.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        results
+    }
+
+    fn positions(&self, term: &str, id: &str) -> &[u32] {
+        self.postings
+            .get(term)
+            .and_then(|postings| postings.get(id))
+            .map_or(&[], Vec::as_slice)
+    }
+
+    fn contains_phrase(&self, id: &str, phrase: &[String]) -> bool {
+        self.positions(&phrase[0], id).iter().any(|&start| {
+            phrase[1..]
+                .iter()
+                .zip(1..)
+                .all(|(term, offset)| self.positions(term, id).contains(&(start + offset)))
+        })
+    }
+
+    fn score(&self, id: &str, query: &Query) -> f64 {
+        let document = &self.documents[id];
+        let count = self.documents.len() as f64;
+        let average_len = self.total_len as f64 / count;
+        let norm = K1 * (1.0 - B + B * f64::from(document.len) / average_len.max(1.0));
+
+        let terms: HashSet<&String> = query.all_terms().collect();
+        terms
+            .into_iter()
+            .map(|term| {
+                let found = self.postings[term].len() as f64;
+                let idf = ((count - found + 0.5) / (found + 0.5) + 1.0).ln();
+                let tf: f64 = self
+                    .positions(term, id)
+                    .iter()
+                    .map(|&p| {
+                        if p < document.title_len {
+                            TITLE_WEIGHT
+                        } else {
+                            1.0
+                        }
+                    })
+                    .sum();
+                idf * tf * (K1 + 1.0) / (tf + norm)
+            })
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Query, SearchIndex};
+
+    fn ids(index: &SearchIndex, query: &str) -> Vec<String> {
+        index
+            .search(&Query::parse(query))
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect()
+    }
+
+    #[test]
+    fn phrases_and_ranking() {
+        let query = Query::parse(r#"parse "hello world" x"#);
+        assert_eq!(query.terms, ["parse", "x"]);
+        assert_eq!(query.phrases, [["hello", "world"]]);
+
+        let mut index = SearchIndex::default();
+        index.insert("a", None, "fn main() {\n    println!(\"hello world\");\n}");
+        index.insert("b", Some("Hello"), "world hello");
+        index.insert("c", None, "nothing to see");
+
+        assert_eq!(ids(&index, "hello"), ["b", "a"]);
+        assert_eq!(ids(&index, "\"hello world\""), ["a"]);
+        assert!(ids(&index, "hello see").is_empty());
+
+        index.remove("a");
+        assert!(ids(&index, "println").is_empty());
+        assert_eq!(
+            Query::parse("println").snippet("a\n  println!(1)\n"),
+            Some(String::from("println!(1)"))
+        );
+    }
+}
@@ -0,0 +1,419 @@
+use crate::entry::{Entry, Revision};
+use crate::Error;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::{Entry as Slot, HashMap};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+pub trait Storage: Send {
+    fn get(&self, id: &str) -> Option<Entry>;
+    // For callers that only need a few fields and shouldn't copy the content.
+    fn get_ref(&self, id: &str) -> Option<&Entry>;
+    fn contains(&self, id: &str) -> bool;
+    fn insert(&mut self, entry: Entry) -> Result<(), Error>;
+    fn replace(&mut self, entry: Entry) -> Result<Entry, Error>;
+    fn remove(&mut self, id: &str) -> Result<Option<Entry>, Error>;
+    // Edits store only the new revision, not the whole entry again.
+    fn push_revision(&mut self, id: &str, revision: Revision) -> Result<(), Error>;
+    // Views are only counted in memory, so a read never waits on the disk.
+    // `flush_views` writes them out in one batch.
+    fn count_view(&mut self, id: &str) -> Result<(), Error>;
+    fn flush_views(&mut self) -> Result<(), Error>;
+    fn entries(&self) -> Box<dyn Iterator<Item = &Entry> + '_>;
+}
+
+#[derive(Default)]
+pub struct MemoryStorage {
+    entries: HashMap<String, Entry>,
+}
+
+impl Storage for MemoryStorage {
+    fn get(&self, id: &str) -> Option<Entry> {
+        self.entries.get(id).cloned()
+    }
+
+    fn get_ref(&self, id: &str) -> Option<&Entry> {
+        self.entries.get(id)
+    }
+
+    fn contains(&self, id: &str) -> bool {
+        self.entries.contains_key(id)
+    }
+
+    fn insert(&mut self, entry: Entry) -> Result<(), Error> {
+        match self.entries.entry(entry.id.clone()) {
+            Slot::Occupied(_) => Err(Error::DuplicateEntry),
+            Slot::Vacant(slot) => {
+                slot.insert(entry);
+                Ok(())
+            }
+        }
+    }
+
+    fn replace(&mut self, entry: Entry) -> Result<Entry, Error> {
+        match self.entries.get_mut(&entry.id) {
+            Some(old) => Ok(std::mem::replace(old, entry)),
+            None => Err(Error::NotFound),
+        }
+    }
+
+    fn remove(&mut self, id: &str) -> Result<Option<Entry>, Error> {
+        Ok(self.entries.remove(id))
+    }
+
+    fn push_revision(&mut self, id: &str, revision: Revision) -> Result<(), Error> {
+        let entry = self.entries.get_mut(id).ok_or(Error::NotFound)?;
+        entry.push_revision(revision);
+        Ok(())
+    }
+
+    fn count_view(&mut self, id: &str) -> Result<(), Error> {
+        let entry = self.entries.get_mut(id).ok_or(Error::NotFound)?;
+        entry.views += 1;
+        Ok(())
+    }
+
+    fn flush_views(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn entries(&self) -> Box<dyn Iterator<Item = &Entry> + '_> {
+        Box::new(self.entries.values())
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum Record {
+    Put { entry: Box<Entry> },
+    Remove { id: String },
+    Revision { id: String, revision: Box<Revision> },
+    Views { counts: HashMap<String, u64> },
+}
+
+// Append-only JSON lines log. The whole log is replayed into memory on open
+// and compacted, so it only ever holds one record per live entry after a
+// restart.
+pub struct LogStorage {
+    entries: HashMap<String, Entry>,
+    log: Log,
+    // views counted since the last flush
+    views: HashMap<String, u64>,
+}
+
+impl LogStorage {
+    pub fn open(path: impl AsRef<Path>) -> Result<LogStorage, Error> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(storage_err)?;
+        }
+
+        let mut entries = HashMap::new();
+        match File::open(path) {
+            Ok(file) => {
+                let mut lines = BufReader::new(file).lines().enumerate().peekable();
+                while let Some((n, line)) = lines.next() {
+                    let line = line.map_err(storage_err)?;
+                    match serde_json::from_str(&line) {
+                        Ok(Record::Put { entry }) => {
+                            entries.insert(entry.id.clone(), *entry);
+                        }
+                        Ok(Record::Remove { id }) => {
+                            entries.remove(&id);
+                        }
+                        Ok(Record::Revision { id, revision }) => {
+                            if let Some(entry) = entries.get_mut(&id) {
+                                entry.push_revision(*revision);
+                            }
+                        }
+                        Ok(Record::Views { counts }) => {
+                            for (id, count) in counts {
+                                if let Some(entry) = entries.get_mut(&id) {
+                                    entry.views += count;
+                                }
+                            }
+                        }
+                        // a torn write from a crash can only leave the last
+                        // line incomplete, anything else is refused rather
+                        // than compacted away
+                        Err(_) if lines.peek().is_none() => {
+                            warn_!("dropping incomplete last record in {}", path.display());
+                        }
+                        Err(e) => {
+                            return Err(storage_err(format!(
+                                "{}: bad record on line {}: {}",
+                                path.display(),
+                                n + 1,
+                                e
+                            )))
+                        }
+                    }
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(storage_err(e)),
+        }
+
+        compact(path, &entries)?;
+        let file = OpenOptions::new()
+            .append(true)
+            .open(path)
+            .map_err(storage_err)?;
+        let len = file.metadata().map_err(storage_err)?.len();
+
+        Ok(LogStorage {
+            entries,
+            log: Log {
+                file,
+                len,
+                broken: false,
+            },
+            views: HashMap::new(),
+        })
+    }
+}
+
+struct Log {
+    file: File,
+    // end of the last complete record
+    len: u64,
+    // set when a failed write couldn't be rolled back
+    broken: bool,
+}
+
+impl Log {
+    // A failed write is cut off again, otherwise the next record would be
+    // appended to the partial line and lost on replay along with it.
+    fn append(&mut self, record: &Record) -> Result<(), Error> {
+        if self.broken {
+            return Err(storage_err("log is unusable after a failed write"));
+        }
+        let mut line = serde_json::to_vec(record).map_err(storage_err)?;
+        line.push(b'\n');
+        let written = self
+            .file
+            .write_all(&line)
+            .and_then(|()| self.file.sync_data());
+        if let Err(e) = written {
+            if self.file.set_len(self.len).is_err() {
+                self.broken = true;
+            }
+            return Err(storage_err(e));
+        }
+        self.len += line.len() as u64;
+        Ok(())
+    }
+}
+
+impl Storage for LogStorage {
+    fn get(&self, id: &str) -> Option<Entry> {
+        self.entries.get(id).cloned()
+    }
+
+    fn get_ref(&self, id: &str) -> Option<&Entry> {
+        self.entries.get(id)
+    }
+
+    fn contains(&self, id: &str) -> bool {
+        self.entries.contains_key(id)
+    }
+
+    fn insert(&mut self, entry: Entry) -> Result<(), Error> {
+        match self.entries.entry(entry.id.clone()) {
+            Slot::Occupied(_) => Err(Error::DuplicateEntry),
+            Slot::Vacant(slot) => {
+                self.log.append(&Record::Put {
+                    entry: Box::new(entry.clone()),
+                })?;
+                slot.insert(entry);
+                Ok(())
+            }
+        }
+    }
+
+    // The full entry already carries its views, pending ones included.
+    fn replace(&mut self, entry: Entry) -> Result<Entry, Error> {
+        match self.entries.get_mut(&entry.id) {
+            Some(old) => {
+                self.log.append(&Record::Put {
+                    entry: Box::new(entry.clone()),
+                })?;
+                self.views.remove(&entry.id);
+                Ok(std::mem::replace(old, entry))
+            }
+            None => Err(Error::NotFound),
+        }
+    }
+
+    fn remove(&mut self, id: &str) -> Result<Option<Entry>, Error> {
+        if !self.entries.contains_key(id) {
+            return Ok(None);
+        }
+        self.log.append(&Record::Remove { id: id.to_string() })?;
+        self.views.remove(id);
+        Ok(self.entries.remove(id))
+    }
+
+    fn push_revision(&mut self, id: &str, revision: Revision) -> Result<(), Error> {
+        let entry = self.entries.get_mut(id).ok_or(Error::NotFound)?;
+        self.log.append(&Record::Revision {
+            id: id.to_string(),
+            revision: Box::new(revision.clone()),
+        })?;
+        entry.push_revision(revision);
+        Ok(())
+    }
+
+    fn count_view(&mut self, id: &str) -> Result<(), Error> {
+        let entry = self.entries.get_mut(id).ok_or(Error::NotFound)?;
+        entry.views += 1;
+        *self.views.entry(id.to_string()).or_default() += 1;
+        Ok(())
+    }
+
+    // Views counted since the last flush are lost if the process dies first.
+    fn flush_views(&mut self) -> Result<(), Error> {
+        if self.views.is_empty() {
+            return Ok(());
+        }
+        // kept on failure, the next flush tries again
+        self.log.append(&Record::Views {
+            counts: self.views.clone(),
+        })?;
+        self.views.clear();
+        Ok(())
+    }
+
+    fn entries(&self) -> Box<dyn Iterator<Item = &Entry> + '_> {
+        Box::new(self.entries.values())
+    }
+}
+
+fn compact(path: &Path, entries: &HashMap<String, Entry>) -> Result<(), Error> {
+    let mut tmp = PathBuf::from(path);
+    tmp.set_extension("compact");
+
+    let mut out = BufWriter::new(File::create(&tmp).map_err(storage_err)?);
+    for entry in entries.values() {
+        serde_json::to_writer(
+            &mut out,
+            &Record::Put {
+                entry: Box::new(entry.clone()),
+            },
+        )
+        .map_err(storage_err)?;
+        out.write_all(b"\n").map_err(storage_err)?;
+    }
+    let out = out.into_inner().map_err(|e| storage_err(e.into_error()))?;
+    out.sync_all().map_err(storage_err)?;
+
+    fs::rename(&tmp, path).map_err(storage_err)
+}
+
+pub fn storage_err(e: impl std::fmt::Display) -> Error {
+    Error::Storage(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LogStorage, Storage};
+    use crate::entry::{Entry, Revision};
+
+    fn entry(id: &str, content: &str) -> Entry {
+        Entry::new(
+            id.to_string(),
+            Revision {
+                content: content.as_bytes().to_vec(),
+                encrypted: false,
+                salt: None,
+                verifier: None,
+                mime: None,
+                filename: None,
+                created_at: 0,
+            },
+        )
+    }
+
+    #[test]
+    fn log_survives_reopen() {
+        let dir = std::env::temp_dir().join(format!("pastebin-log-{}", std::process::id()));
+        let path = dir.join("pastes.log");
+        let _ = std::fs::remove_file(&path);
+
+        let mut storage = LogStorage::open(&path).unwrap();
+        storage.insert(entry("a", "first")).unwrap();
+        storage.insert(entry("b", "second")).unwrap();
+        assert!(storage.insert(entry("a", "clobber")).is_err());
+        storage.replace(entry("a", "third")).unwrap();
+        let edit = entry("a", "fourth").current;
+        storage.push_revision("a", edit).unwrap();
+        storage.insert(entry("d", "gone")).unwrap();
+        storage.remove("d").unwrap();
+        storage.count_view("b").unwrap();
+        storage.count_view("b").unwrap();
+        storage.flush_views().unwrap();
+        // not flushed, so it doesn't survive
+        storage.count_view("b").unwrap();
+        drop(storage);
+
+        let storage = LogStorage::open(&path).unwrap();
+        let a = storage.get("a").unwrap();
+        assert_eq!(a.current.content, b"fourth");
+        assert_eq!(a.history[0].content, b"third");
+        assert_eq!(storage.get("b").unwrap().current.content, b"second");
+        assert_eq!(storage.get("b").unwrap().views, 2);
+        assert!(storage.get("c").is_none());
+        assert!(storage.get("d").is_none());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn only_a_torn_last_record_is_dropped() {
+        let dir = std::env::temp_dir().join(format!("pastebin-torn-{}", std::process::id()));
+        let path = dir.join("pastes.log");
+        let _ = std::fs::remove_file(&path);
+
+        let mut storage = LogStorage::open(&path).unwrap();
+        storage.insert(entry("a", "first")).unwrap();
+        drop(storage);
+        let log = std::fs::read_to_string(&path).unwrap();
+
+        std::fs::write(&path, format!("{}{{\"op\":\"put\",\"ent", log)).unwrap();
+        let storage = LogStorage::open(&path).unwrap();
+        assert!(storage.get("a").is_some());
+        drop(storage);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), log);
+
+        std::fs::write(&path, format!("{{\"op\":\"unknown\"}}\n{}", log)).unwrap();
+        assert!(LogStorage::open(&path).is_err());
+        assert!(std::fs::read_to_string(&path).unwrap().contains("unknown"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn entries_from_before_binary_content_still_load() {
+        let dir = std::env::temp_dir().join(format!("pastebin-legacy-{}", std::process::id()));
+        let path = dir.join("pastes.log");
+        std::fs::create_dir_all(&dir).unwrap();
+        // plain text was stored as is, encrypted content base64 encoded
+        let log = [
+            r#"{"op":"put","entry":{"id":"text","content":"abcd","encrypted":false,"salt":null,"verifier":null,"created_at":5,"history":[{"content":"hello","encrypted":false,"salt":null,"verifier":null,"created_at":1}],"edit_token":null,"delete_token":null,"expires_at":null,"reads_left":null}}"#,
+            r#"{"op":"put","entry":{"id":"secret","content":"AAEC","encrypted":true,"salt":"c2FsdA==","verifier":"dg==","history":[],"edit_token":null,"delete_token":null,"expires_at":null,"reads_left":null}}"#,
+        ];
+        std::fs::write(&path, log.join("\n") + "\n").unwrap();
+
+        for _ in 0..2 {
+            let storage = LogStorage::open(&path).unwrap();
+            let text = storage.get("text").unwrap();
+            assert_eq!(text.current.content, b"abcd");
+            assert_eq!(text.history[0].content, b"hello");
+            assert_eq!(text.created_at(), 1);
+            assert_eq!(storage.get("secret").unwrap().current.content, [0, 1, 2]);
+        }
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,61 @@
+"use strict";
+
+const form = document.getElementById("create");
+const error = document.getElementById("error");
+
+async function failure(res) {
+    try {
+        return (await res.json()).message;
+    } catch {
+        return res.statusText || "request failed";
+    }
+}
+
+form.addEventListener("submit", async (event) => {
+    event.preventDefault();
+    error.hidden = true;
+
+    const password = document.getElementById("password").value;
+    const expiry = document.getElementById("expiry").value;
+    const body = {
+        content: document.getElementById("content").value,
+        language: document.getElementById("language").value.trim() || null,
+        expires_in: expiry ? Number(expiry) : null,
+        burn_after_reading: document.getElementById("burn").checked,
+        encrypted: password !== "",
+        key: password || null,
+    };
+
+    const button = form.querySelector("button[type=submit]");
+    button.disabled = true;
+    try {
+        const res = await fetch("/api/add", {
+            method: "POST",
+            headers: { "Content-Type": "application/json" },
+            body: JSON.stringify(body),
+        });
+        if (!res.ok) {
+            throw new Error(await failure(res));
+        }
+        const created = await res.json();
+        const url = new URL(`/view.html?id=${encodeURIComponent(created.id)}`, location.href);
+        const link = document.getElementById("link");
+        link.href = url;
+        link.textContent = url;
+        document.getElementById("edit-token").textContent = created.edit_token;
+        document.getElementById("delete-token").textContent = created.delete_token;
+        form.hidden = true;
+        document.getElementById("created").hidden = false;
+    } catch (e) {
+        error.textContent = e.message;
+        error.hidden = false;
+    } finally {
+        button.disabled = false;
+    }
+});
+
+document.getElementById("copy-link").addEventListener("click", (event) => {
+    navigator.clipboard.writeText(document.getElementById("link").href).then(() => {
+        event.target.textContent = "Copied";
+    });
+});
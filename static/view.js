@@ -0,0 +1,125 @@
+"use strict";
+
+const params = new URLSearchParams(location.search);
+const id = params.get("id") || "";
+const rev = params.get("rev");
+const query = `id=${encodeURIComponent(id)}` + (rev ? `&rev=${encodeURIComponent(rev)}` : "");
+
+const error = document.getElementById("error");
+const decryptForm = document.getElementById("decrypt");
+let entry = null;
+let text = null;
+
+function showError(message) {
+    error.textContent = message;
+    error.hidden = false;
+}
+
+async function failure(res) {
+    try {
+        return (await res.json()).message;
+    } catch {
+        return res.statusText || "request failed";
+    }
+}
+
+function decodeBase64(content) {
+    return Uint8Array.from(atob(content), (c) => c.charCodeAt(0));
+}
+
+// Raw and download work from the content already loaded instead of linking
+// to /raw and /download, so they still work for encrypted pastes and don't
+// use up another read on pastes with a read limit. Blob URLs share our
+// origin, so the uploader's content type is never used for them.
+function show(bytes, isText) {
+    const type = isText ? "text/plain;charset=utf-8" : "application/octet-stream";
+    const blob = new Blob([bytes], { type });
+    const url = URL.createObjectURL(blob);
+    const raw = document.getElementById("raw");
+    raw.href = url;
+    const download = document.getElementById("download");
+    download.href = url;
+    download.download = entry.filename || (isText ? `${entry.id}.txt` : entry.id);
+
+    const content = document.getElementById("content");
+    if (isText) {
+        content.textContent = text;
+    } else {
+        content.textContent = `Binary content, ${blob.size} bytes.`;
+        document.getElementById("copy").hidden = true;
+    }
+    document.getElementById("paste").hidden = false;
+}
+
+async function load() {
+    const res = await fetch(`/api/get?${query}`);
+    if (!res.ok) {
+        throw new Error(await failure(res));
+    }
+    entry = await res.json();
+
+    const title = entry.title || entry.id;
+    document.title = `${title} - pastebin`;
+    document.getElementById("title").textContent = title;
+    const meta = [
+        entry.language,
+        `revision ${entry.revision}`,
+        new Date(entry.created_at * 1000).toLocaleString(),
+        `${entry.views} ${entry.views === 1 ? "view" : "views"}`,
+    ];
+    document.getElementById("meta").textContent = meta.filter(Boolean).join(" · ");
+
+    if (entry.encrypted) {
+        decryptForm.hidden = false;
+        document.getElementById("password").focus();
+    } else if (entry.encoding === "utf8") {
+        text = entry.content;
+        // The highlighted page is another read, which a read-limited paste
+        // may not have left.
+        if (entry.reads_left === null) {
+            const highlighted = document.getElementById("highlighted");
+            highlighted.href = `/p/${encodeURIComponent(entry.id)}?rev=${entry.revision}`;
+            highlighted.hidden = false;
+        }
+        show(text, true);
+    } else {
+        show(decodeBase64(entry.content), false);
+    }
+}
+
+decryptForm.addEventListener("submit", async (event) => {
+    event.preventDefault();
+    error.hidden = true;
+    const button = decryptForm.querySelector("button");
+    button.disabled = true;
+    try {
+        const res = await fetch(`/api/decrypt?id=${encodeURIComponent(entry.id)}&rev=${entry.revision}`, {
+            method: "POST",
+            headers: { "Content-Type": "application/json" },
+            body: JSON.stringify({ key: document.getElementById("password").value }),
+        });
+        if (!res.ok) {
+            throw new Error(await failure(res));
+        }
+        const bytes = new Uint8Array(await res.arrayBuffer());
+        try {
+            text = new TextDecoder("utf-8", { fatal: true }).decode(bytes);
+        } catch {
+            text = null;
+        }
+        decryptForm.hidden = true;
+        show(bytes, text !== null);
+    } catch (e) {
+        showError(e.message);
+    } finally {
+        button.disabled = false;
+    }
+});
+
+document.getElementById("copy").addEventListener("click", (event) => {
+    navigator.clipboard.writeText(text).then(() => {
+        event.target.textContent = "Copied";
+    });
+});
+
+load().catch((e) => showError(e.message));